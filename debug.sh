@@ -45,6 +45,14 @@ test_sound_types() {
     cargo run "./homados Output/debug" "debug sound 026--violet" -t violet
     cargo run "./homados Output/debug" "debug sound 027a--pseudo velvet 0.5" -t pseudo_velvet --p1 0.5
     cargo run "./homados Output/debug" "debug sound 027b--pseudo velvet 0.99" -t pseudo_velvet --p1 0.995
+    cargo run "./homados Output/debug" "debug sound 028--sawtooth band-limited 440hz" -t saw_bl
+    cargo run "./homados Output/debug" "debug sound 029--square band-limited 440hz" -t sqr_bl
+    cargo run "./homados Output/debug" "debug sound 030--triangle band-limited 440hz" -t tri_bl
+    cargo run "./homados Output/debug" "debug sound 031--pwm band-limited 440hz" -t pwm_bl --p1 0.25
+    cargo run "./homados Output/debug" "debug sound 032--sharktooth band-limited 440hz" -t shark_bl
+    cargo run "./homados Output/debug" "debug sound 033--dirac comb naive 440hz" -t comb_naive
+    cargo run "./homados Output/debug" "debug sound 034--dirac comb sweep lin" -t comb_lin
+    cargo run "./homados Output/debug" "debug sound 035--dirac comb sweep exp" -t comb_exp
 }
 
 # Function to check all the window types
@@ -133,6 +141,96 @@ test_window_types() {
     cargo run "./homados Output/debug" "debug window 076--slg-oi" -t sine -w slg_oi
 }
 
+# Function to check all the channel modes
+test_channel_modes() {
+    # Announce task, then perform
+    printf -- "\n>------------------------------------------------------<\n"
+    printf -- " Testing all channel modes on 10 second duration pink.\n"
+    printf -- ">------------------------------------------------------<\n\n"
+
+    cargo run "./homados Output/debug" "debug channels 000--duplicate stereo" -t pink -c 2 --ChannelMode duplicate
+    cargo run "./homados Output/debug" "debug channels 001--independent stereo" -t pink -c 2 --ChannelMode independent
+    cargo run "./homados Output/debug" "debug channels 002--first-only stereo" -t pink -c 2 --ChannelMode first-only
+    cargo run "./homados Output/debug" "debug channels 003--independent 6 channel" -t pink -c 6 --ChannelMode independent
+    cargo run "./homados Output/debug" "debug channels 004--correlated 0.5 stereo" -t pink -c 2 --Correlation 0.5
+    cargo run "./homados Output/debug" "debug channels 005--correlated 0.9 stereo brown" -t brown -c 2 --Correlation 0.9
+    cargo run "./homados Output/debug" "debug channels 006--per-channel sine pink silence" -c 3 -t sine,pink,silence -f 1000 -G 0,-20,0
+    cargo run "./homados Output/debug" "debug channels 007--per-channel sweep windows" -c 2 -t sweep_lin_sin -w lin_in,lin_out --p1 20,1000
+    cargo run "./homados Output/debug" "debug channels 008--layout 5.1" -t pink --Layout 5.1 --ChannelMode independent
+    cargo run "./homados Output/debug" "debug channels 009--layout 7.1.4" -t pink --Layout 7.1.4 --ChannelMode independent
+    cargo run "./homados Output/debug" "debug channels 010--layout ambisonic" -t pink --Layout ambisonic --ChannelMode first-only
+}
+
+# Function to check all the output formats
+test_formats() {
+    # Announce task, then perform
+    printf -- "\n>------------------------------------------------------<\n"
+    printf -- " Testing all output formats on 10 second duration pink.\n"
+    printf -- ">------------------------------------------------------<\n\n"
+
+    cargo run "./homados Output/debug" "debug format 000--wav 16 bit stereo.wav" -t pink -c 2 -b 16 --ChannelMode independent --Seed 1
+    cargo run "./homados Output/debug" "debug format 000--flac 16 bit stereo.flac" -t pink -c 2 -b 16 --ChannelMode independent --Seed 1
+    cargo run "./homados Output/debug" "debug format 001--wav 24 bit 5.1" -t pink --Layout 5.1 --ChannelMode independent --Seed 1
+    cargo run "./homados Output/debug" "debug format 001--flac 24 bit 5.1" -t pink --Layout 5.1 --ChannelMode independent --Seed 1 --Format flac
+    cargo run "./homados Output/debug" "debug format 002--flac 24 bit sine.flac" -t sine -f 997
+    cargo run "./homados Output/debug" "debug format 003--aiff 16 bit stereo.aiff" -t pink -c 2 -b 16 --ChannelMode independent --Seed 1
+    cargo run "./homados Output/debug" "debug format 004--aiff-c 32 bit float.aiff" -t pink -b 32f
+    cargo run "./homados Output/debug" "debug format 005--aiff loop saw 440hz.aiff" -t saw_bl -f 440 --Loop
+    cargo run "./homados Output/debug" "debug format 006--rf64 24 bit 5.1" -t pink --Layout 5.1 --ChannelMode independent --Seed 1 --Format rf64
+    cargo run "./homados Output/debug" "debug format 007--w64 24 bit 5.1.w64" -t pink --Layout 5.1 --ChannelMode independent --Seed 1
+    cargo run "./homados Output/debug" "debug format 008--wav loop saw 440hz.wav" -t saw_bl -f 440 --Loop
+    cargo run "./homados Output/debug" "debug format 009--wav loop cosine 441hz.wav" -t cosine -f 441 --Loop
+    cargo run "./homados Output/debug" "debug format 011--wav markers exp sweep.wav" -t sweep_exp_sin --Markers octave,window -w sc1_io
+    cargo run "./homados Output/debug" "debug format 012--wav markers pulse width sweep.wav" -t pulse_width_sweep --p1 0.1 --p2 0.9 --Markers 0.25,0.5,0.75
+
+    # A FLAC decodes to exactly the samples of the WAV rendered with the same seed. The decoder
+    # checks the MD5 as it goes, and the samples are compared against the end of the WAV, where
+    # its data chunk is.
+    if command -v flac > /dev/null
+    then
+        for test in "000--" "001--"
+        do
+            flac -d -s -f --force-raw-format --endian=little --sign=signed \
+                -o "./homados Output/debug/debug.raw" "./homados Output/debug/debug format $test"*.flac
+            wav=("./homados Output/debug/debug format $test"*.wav)
+            if cmp -s <(tail -c "$(stat -c %s "./homados Output/debug/debug.raw")" "${wav[0]}") "./homados Output/debug/debug.raw"
+            then
+                printf -- " - FLAC round trip %s: passed\n" "$test"
+            else
+                printf -- " - FLAC round trip %s: FAILED\n" "$test"
+            fi
+        done
+        rm -f "./homados Output/debug/debug.raw"
+    else
+        printf -- " - flac is not installed, skipping the FLAC round trip (cargo test still checks the encoder)\n"
+    fi
+
+    # A streamed WAV holds the same samples as a written one, and raw PCM is the same as its data
+    # chunk. Only the samples are compared, as the tags in the header say how each was made.
+    cargo run "./homados Output/debug" "debug format 001--wav 24 bit 5.1" -t pink --Layout 5.1 --ChannelMode independent --Seed 1 \
+        --Stdout > "./homados Output/debug/debug.wav"
+    cargo run "./homados Output/debug" "debug format 001--raw 24 bit 5.1" -t pink --Layout 5.1 --ChannelMode independent --Seed 1 \
+        --Stdout --Format s24le > "./homados Output/debug/debug.raw"
+    size="$(stat -c %s "./homados Output/debug/debug.raw")"
+    if cmp -s <(tail -c "$size" "./homados Output/debug/debug.wav") "./homados Output/debug/debug.raw" \
+        && cmp -s <(tail -c "$size" "./homados Output/debug/debug format 001--wav 24 bit 5.1.wav") "./homados Output/debug/debug.raw"
+    then
+        printf -- " - Streaming to stdout: passed\n"
+    else
+        printf -- " - Streaming to stdout: FAILED\n"
+    fi
+    rm -f "./homados Output/debug/debug.wav" "./homados Output/debug/debug.raw"
+
+    # A render without a seed is reproduced from the seed and command stored in it
+    cargo run "./homados Output/debug" "debug format 010--reproduce white.wav" -t white -d 1
+    if cargo run reproduce "./homados Output/debug/debug format 010--reproduce white.wav" | grep -q "bit-identical"
+    then
+        printf -- " - Reproducing a render: passed\n"
+    else
+        printf -- " - Reproducing a render: FAILED\n"
+    fi
+}
+
 conclude() {
     # Announce Completion, then exit
     printf -- "\n>------------------------------------------------------<\n"
@@ -159,6 +257,8 @@ if [ $FULL -lt 1 ]
 then
     test_sound_types
     test_window_types
+    test_channel_modes
+    test_formats
     conclude
 fi
 
@@ -184,6 +284,28 @@ case "$response" in
         ;;
 esac
 
+# Channel mode test prompt
+read -r -p "Test channel modes? [y/N] " response
+case "$response" in
+    [yY][eE][sS]|[yY]) 
+        CHANNEL=0
+        ;;
+    *)
+        CHANNEL=1
+        ;;
+esac
+
+# Output format test prompt
+read -r -p "Test output formats? [y/N] " response
+case "$response" in
+    [yY][eE][sS]|[yY]) 
+        FORMAT=0
+        ;;
+    *)
+        FORMAT=1
+        ;;
+esac
+
 # Test Sound Types
 if [ $SOUND -lt 1 ]
 then
@@ -196,4 +318,16 @@ then
     test_window_types
 fi
 
+# Test Channel Modes
+if [ $CHANNEL -lt 1 ]
+then
+    test_channel_modes
+fi
+
+# Test Output Formats
+if [ $FORMAT -lt 1 ]
+then
+    test_formats
+fi
+
 conclude
\ No newline at end of file
@@ -0,0 +1,191 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//         T H E   A I F F   W R I T E R       //
+//                                             //
+/////////////////////////////////////////////////
+
+// References:
+// [15] Apple, "Audio Interchange File Format: AIFF", version 1.3, 1989
+//  - https://www.loc.gov/preservation/digital/formats/fdd/fdd000005.shtml
+// [16] Apple, "Audio Interchange File Format AIFF-C", draft of 1991
+//  - https://www.loc.gov/preservation/digital/formats/fdd/fdd000006.shtml
+
+// Integer output is written as plain AIFF, which every sampler that reads AIFF at all can load.
+// Float output needs AIFF-C, with the "fl32" or "fl64" compression type. Everything is big-endian,
+// and the sample rate is an 80 bit extended float. AIFF has no channel mask, so channels are
+// written in the same order as they are in a WAV file, and it's up to the reader to place them.
+// A loop is written as two markers, with an INST chunk naming them as its sustain loop.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use crate::format::Loop;
+use crate::wav::{SampleFormat, Spec};
+
+// Writes interleaved samples to an AIFF file, then fills in the frame count and chunk sizes once
+// it's finalized. Like the WAV writer, it writes to a ".part" file until then.
+pub struct AiffWriter {
+    file: BufWriter<File>,
+    path: String,
+    finished: bool,
+    spec: Spec,
+    data_len: u32,
+    frames_offset: u64,
+    data_len_offset: u64,
+}
+
+impl AiffWriter {
+    pub fn create(path: &str, spec: Spec, looped: Option<Loop>) -> io::Result<AiffWriter> {
+        if !spec.sample_format.supports(spec.bits_per_sample) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported bit depth"));
+        }
+        let mut file = BufWriter::new(File::create(path.to_string() + ".part")?);
+        let float = spec.sample_format == SampleFormat::Float;
+
+        file.write_all(b"FORM")?;
+        file.write_all(&0u32.to_be_bytes())?;
+        file.write_all(if float {b"AIFC"} else {b"AIFF"})?;
+        if float {
+            // The one version of AIFF-C there is
+            file.write_all(b"FVER")?;
+            file.write_all(&4u32.to_be_bytes())?;
+            file.write_all(&0xa2805140u32.to_be_bytes())?;
+        }
+
+        // The frame count is filled in on finalizing, like the sound data length.
+        let name: &[u8] = match spec.bits_per_sample {
+            32 => b"32-bit floating point",
+            _ => b"64-bit floating point",
+        };
+        file.write_all(b"COMM")?;
+        file.write_all(&(if float {18 + 4 + pstring_len(name)} else {18u32}).to_be_bytes())?;
+        file.write_all(&spec.channels.to_be_bytes())?;
+        let frames_offset = file.stream_position()?;
+        file.write_all(&0u32.to_be_bytes())?;
+        file.write_all(&spec.bits_per_sample.to_be_bytes())?;
+        file.write_all(&extended(spec.sample_rate as f64))?;
+        if float {
+            file.write_all(if spec.bits_per_sample == 32 {b"fl32"} else {b"fl64"})?;
+            write_pstring(&mut file, name)?;
+        }
+
+        // Loop markers go at the loop's first frame and just after its last
+        if let Some(looped) = looped {
+            let names: [&[u8]; 2] = [b"Loop Start", b"Loop End"];
+            file.write_all(b"MARK")?;
+            file.write_all(&(2 + names.iter().map(|name| 6 + pstring_len(name)).sum::<u32>()).to_be_bytes())?;
+            file.write_all(&2u16.to_be_bytes())?;
+            for (id, (position, name)) in [looped.start, looped.end].iter().zip(names).enumerate() {
+                file.write_all(&(id as u16 + 1).to_be_bytes())?;
+                file.write_all(&position.to_be_bytes())?;
+                write_pstring(&mut file, name)?;
+            }
+
+            // The base note is the nearest MIDI note to the loop's frequency, detuned to match it.
+            // It plays at any note and velocity, at unity gain, with no release loop.
+            let (note, cents) = looped.midi_note();
+            file.write_all(b"INST")?;
+            file.write_all(&20u32.to_be_bytes())?;
+            file.write_all(&[note, cents as u8, 0, 127, 1, 127])?;
+            file.write_all(&0i16.to_be_bytes())?;
+            for (mode, begin, end) in [(1u16, 1u16, 2u16), (0, 0, 0)] {
+                file.write_all(&mode.to_be_bytes())?;
+                file.write_all(&begin.to_be_bytes())?;
+                file.write_all(&end.to_be_bytes())?;
+            }
+        }
+
+        // The offset and block size of the sound data, both 0 as the samples aren't aligned
+        file.write_all(b"SSND")?;
+        file.write_all(&8u32.to_be_bytes())?;
+        let data_len_offset = file.stream_position()? - 4;
+        file.write_all(&0u32.to_be_bytes())?;
+        file.write_all(&0u32.to_be_bytes())?;
+
+        Ok(AiffWriter { file, path: path.to_string(), finished: false, spec, data_len: 0, frames_offset, data_len_offset })
+    }
+
+    // Write one integer sample, already quantized to the bit depth. 8 bit AIFF is signed, unlike WAV.
+    pub fn write_int(&mut self, sample: i32) -> io::Result<()> {
+        let bytes_per_sample = self.count_sample()? as usize;
+        self.file.write_all(&sample.to_be_bytes()[4 - bytes_per_sample ..])
+    }
+
+    // Write one float sample, where 1.0 is full scale. It is written as it is, over-range or not.
+    pub fn write_float(&mut self, sample: f64) -> io::Result<()> {
+        if self.count_sample()? == 4 {
+            self.file.write_all(&(sample as f32).to_be_bytes())
+        } else {
+            self.file.write_all(&sample.to_be_bytes())
+        }
+    }
+
+    // Add one sample to the data length, which has to fit in the 32 bit size field
+    fn count_sample(&mut self) -> io::Result<u16> {
+        let bytes_per_sample = self.spec.bits_per_sample.div_ceil(8);
+        self.data_len = self.data_len.checked_add(bytes_per_sample as u32)
+            .filter(|data_len| *data_len as u64 + self.data_len_offset + 13 <= u32::MAX as u64)
+            .ok_or(io::Error::new(io::ErrorKind::FileTooLarge, "too much data for an AIFF file"))?;
+        Ok(bytes_per_sample)
+    }
+
+    // Pad the sound data to an even length, fill in the frame count and the FORM and SSND sizes,
+    // then move the file into place
+    pub fn finalize(mut self) -> io::Result<()> {
+        if self.data_len % 2 == 1 {
+            self.file.write_all(&[0])?;
+        }
+        let form_len = self.data_len_offset as u32 + 4 + 8 + self.data_len + self.data_len % 2 - 8;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&form_len.to_be_bytes())?;
+        let frames = self.data_len / (self.spec.bits_per_sample.div_ceil(8) * self.spec.channels) as u32;
+        self.file.seek(SeekFrom::Start(self.frames_offset))?;
+        self.file.write_all(&frames.to_be_bytes())?;
+        self.file.seek(SeekFrom::Start(self.data_len_offset))?;
+        self.file.write_all(&(8 + self.data_len).to_be_bytes())?;
+        self.file.flush()?;
+        fs::rename(self.path.clone() + ".part", &self.path)?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for AiffWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = fs::remove_file(self.path.clone() + ".part");
+        }
+    }
+}
+
+// Whether this many frames fit in an AIFF file, whose sizes are all 32 bit. The header before the
+// samples is never more than 256 bytes, loop and all.
+pub fn holds(spec: &Spec, frames: u64) -> bool {
+    frames.saturating_mul((spec.bits_per_sample.div_ceil(8) * spec.channels) as u64) <= u32::MAX as u64 - 256
+}
+
+// A positive value as an 80 bit IEEE 754 extended float: a 15 bit exponent, then a 64 bit mantissa
+// with its leading 1 written out rather than implied
+fn extended(value: f64) -> [u8; 10] {
+    let mut bytes = [0u8; 10];
+    if value > 0.0 {
+        let bits = value.to_bits();
+        let exponent = (((bits >> 52) & 0x7ff) as i32 - 1023 + 16383) as u16;
+        let mantissa = ((bits & ((1 << 52) - 1)) | (1 << 52)) << 11;
+        bytes[..2].copy_from_slice(&exponent.to_be_bytes());
+        bytes[2..].copy_from_slice(&mantissa.to_be_bytes());
+    }
+    bytes
+}
+
+// A Pascal string is its length in one byte, then the text, padded to an even length
+fn pstring_len(text: &[u8]) -> u32 {
+    (text.len() as u32 + 2) & !1
+}
+
+fn write_pstring(file: &mut impl Write, text: &[u8]) -> io::Result<()> {
+    file.write_all(&[text.len() as u8])?;
+    file.write_all(text)?;
+    if text.len().is_multiple_of(2) {file.write_all(&[0])?}
+    Ok(())
+}
@@ -0,0 +1,241 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//      D I T H E R   &   Q U A N T I Z E      //
+//                                             //
+/////////////////////////////////////////////////
+
+// References:
+// [6] Lipshitz, Wannamaker & Vanderkooy, "Quantization and Dither: A Theoretical Survey", JAES 1992
+// [7] The F-weighted noise shaping filter is the 9 coefficient design of Wannamaker, as used by SoX:
+//  - https://sourceforge.net/p/sox/code/ci/master/tree/src/dither.h
+
+// Integer output has to land on the grid of its bit depth. Rounding alone leaves an error that
+// follows the signal, which is heard as distortion on quiet sines and long fades. Adding dither
+// before rounding turns that error into a steady noise floor, and noise shaping can then push that
+// floor up towards the frequencies we hear least.
+
+use rand::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    None,
+    Rpdf,
+    Tpdf,
+    HpTpdf,
+}
+
+// All dither names and aliases. The first name of each entry is its canonical name.
+pub const DITHERS: &[(Dither, &[&str])] = &[
+    (Dither::None, &["none", "off"]),
+    (Dither::Rpdf, &["rpdf", "rect", "rectangular"]),
+    (Dither::Tpdf, &["tpdf", "tri", "triangular"]),
+    (Dither::HpTpdf, &["hp-tpdf", "hp_tpdf", "hptpdf"]),
+];
+
+impl Dither {
+    // Look up a dither by any of its names
+    pub fn from_name(name: &str) -> Option<Dither> {
+        DITHERS.iter().find(|(_, names)| names.contains(&name)).map(|(dither, _)| *dither)
+    }
+
+    // The canonical name of this dither
+    pub fn name(self) -> &'static str {
+        DITHERS.iter().find(|(dither, _)| *dither == self).map(|(_, names)| names[0]).unwrap_or("")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseShape {
+    None,
+    Simple,
+    FWeighted,
+}
+
+// All noise shaping names and aliases. The first name of each entry is its canonical name.
+pub const NOISE_SHAPES: &[(NoiseShape, &[&str])] = &[
+    (NoiseShape::None, &["none", "off", "flat"]),
+    (NoiseShape::Simple, &["simple", "error-feedback", "first-order"]),
+    (NoiseShape::FWeighted, &["f-weighted", "f_weighted", "fweighted"]),
+];
+
+impl NoiseShape {
+    // Look up a noise shape by any of its names
+    pub fn from_name(name: &str) -> Option<NoiseShape> {
+        NOISE_SHAPES.iter().find(|(_, names)| names.contains(&name)).map(|(shape, _)| *shape)
+    }
+
+    // The canonical name of this noise shape
+    pub fn name(self) -> &'static str {
+        NOISE_SHAPES.iter().find(|(shape, _)| *shape == self).map(|(_, names)| names[0]).unwrap_or("")
+    }
+
+    // Error feedback coefficients, newest error first. The quantization noise ends up filtered by
+    // 1 - sum(h[k] z^-(k+1)), so the simple filter is a first order high-pass, while the F-weighted
+    // filter [7] follows the inverse of our hearing threshold. It was designed for 44.1kHz, and
+    // stays close to that at 48kHz.
+    fn coefficients(self) -> &'static [f64] {
+        match self {
+            NoiseShape::None => &[],
+            NoiseShape::Simple => &[1.0],
+            NoiseShape::FWeighted => &[2.412, -3.370, 3.937, -4.174, 3.353, -2.205, 1.281, -0.569, 0.0847],
+        }
+    }
+}
+
+// Rounds full scale samples onto the integer grid of a bit depth, with the chosen dither and noise
+// shaping. Every channel keeps its own shaping state, while all of them draw from the one generator
+// given, split off the seeded generator of the render, so a render with a given seed is always
+// dithered the same way.
+pub struct Quantizer {
+    pub dither: Dither,
+    pub shape: NoiseShape,
+    scale: f64,
+    rng: StdRng,
+    last: Vec<f64>,
+    errors: Vec<[f64; 9]>,
+}
+
+impl Quantizer {
+    pub fn new(dither: Dither, shape: NoiseShape, bits_per_sample: u16, channels: u16, rng: StdRng) -> Quantizer {
+        Quantizer {
+            dither,
+            shape,
+            scale: 2.0f64.powf(bits_per_sample as f64 - 1.0) - 1.0,
+            rng,
+            last: vec![0.0; channels as usize],
+            errors: vec![[0.0; 9]; channels as usize],
+        }
+    }
+
+    // The dither for the next sample of the given channel, in LSBs. It is +-0.5 for RPDF and +-1.0
+    // for TPDF, with HP-TPDF taking the difference of successive RPDF values to tilt its noise
+    // towards the top of the spectrum.
+    fn dither(&mut self, channel: usize) -> f64 {
+        match self.dither {
+            Dither::None => 0.0,
+            Dither::Rpdf => self.rng.random::<f64>() - 0.5,
+            Dither::Tpdf => self.rng.random::<f64>() + self.rng.random::<f64>() - 1.0,
+            Dither::HpTpdf => {
+                let r = self.rng.random::<f64>() - 0.5;
+                let d = r - self.last[channel];
+                self.last[channel] = r;
+                d
+            }
+        }
+    }
+
+    // Quantize one sample of the given channel, where 1.0 is full scale
+    pub fn quantize(&mut self, sample: f64, channel: usize) -> i32 {
+        let x = sample * self.scale;
+        let d = self.dither(channel);
+        let errors = &mut self.errors[channel];
+        let v = x - self.shape.coefficients().iter().zip(errors.iter()).map(|(h, e)| h * e).sum::<f64>();
+        let q = (v + d).round();
+
+        // Keep the error from before clipping, so a clipped sample can't throw the shaping off.
+        errors.rotate_right(1);
+        errors[0] = q - v;
+        q.clamp(-self.scale, self.scale) as i32
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::Sound;
+
+    fn quantizer(dither: Dither, shape: NoiseShape, seed: u64) -> Quantizer {
+        Quantizer::new(dither, shape, 16, 1, Sound::dither_rng(seed))
+    }
+
+    // The mean and variance of some values, and the correlation of each with the next
+    fn statistics(values: &[f64]) -> (f64, f64, f64) {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        let lag = values.windows(2).map(|pair| (pair[0] - mean) * (pair[1] - mean)).sum::<f64>() / (n - 1.0);
+        (mean, variance, lag / variance)
+    }
+
+    // RPDF is 1 LSB wide with a variance of 1/12, and TPDF 2 LSBs wide with a variance of 1/6 [6].
+    // HP-TPDF has the same amplitude as TPDF, but each value is anticorrelated with the next.
+    #[test]
+    fn dither_amplitude() {
+        for (dither, width, variance, correlation) in [(Dither::Rpdf, 0.5, 1.0 / 12.0, 0.0),
+                (Dither::Tpdf, 1.0, 1.0 / 6.0, 0.0), (Dither::HpTpdf, 1.0, 1.0 / 6.0, -0.5)] {
+            let mut quantizer = quantizer(dither, NoiseShape::None, 5);
+            let values: Vec<f64> = (0 .. 200000).map(|_| quantizer.dither(0)).collect();
+            assert!(values.iter().all(|v| v.abs() <= width), "{} within +-{}", dither.name(), width);
+            assert!(values.iter().any(|v| v.abs() > 0.95 * width), "{} reaches +-{}", dither.name(), width);
+            let (mean, measured, lag) = statistics(&values);
+            assert!(mean.abs() < 0.005, "{} mean {}", dither.name(), mean);
+            assert!((measured / variance - 1.0).abs() < 0.02, "{} variance {}", dither.name(), measured);
+            assert!((lag - correlation).abs() < 0.01, "{} correlation {}", dither.name(), lag);
+        }
+        assert_eq!(quantizer(Dither::None, NoiseShape::None, 5).dither(0), 0.0);
+    }
+
+    #[test]
+    fn dither_is_seeded() {
+        let quantize = |seed: u64| {
+            let mut quantizer = quantizer(Dither::Tpdf, NoiseShape::FWeighted, seed);
+            (0 .. 4800).map(|i| quantizer.quantize(1e-4 * (i as f64 * 0.05).sin(), 0)).collect::<Vec<i32>>()
+        };
+        assert_eq!(quantize(5), quantize(5));
+        assert_ne!(quantize(5), quantize(6));
+    }
+
+    // A sine a few LSBs high is rounded to the nearest step. Truncating towards zero would pull
+    // every sample towards the middle, by half a step on average, where rounding leaves no more
+    // than a few hundredths from where the steps happen to fall on the sine.
+    #[test]
+    fn rounds_rather_than_truncates() {
+        let mut quantizer = quantizer(Dither::None, NoiseShape::None, 5);
+        let scale = 32767.0;
+        let sine: Vec<f64> = (0 .. 48000).map(|i| 3.3 / scale * (i as f64 * 997.0 / 48000.0 * std::f64::consts::TAU).sin()).collect();
+        let errors: Vec<f64> = sine.iter().map(|&x| quantizer.quantize(x, 0) as f64 - x * scale).collect();
+        assert!(errors.iter().all(|e| e.abs() <= 0.5));
+        let bias = sine.iter().zip(&errors).map(|(x, e)| x.signum() * e).sum::<f64>() / sine.len() as f64;
+        assert!(bias.abs() < 0.1, "bias towards zero of {} LSB", bias);
+        assert_eq!((quantizer.quantize(0.7 / scale, 0), quantizer.quantize(-0.7 / scale, 0)), (1, -1));
+        assert_eq!((quantizer.quantize(1.5, 0), quantizer.quantize(-1.5, 0)), (32767, -32767));
+    }
+
+    // The gain of a noise shaping filter at a frequency, 1 - sum(h[k] z^-(k+1)), in dB
+    fn noise_gain(shape: NoiseShape, frequency: f64, sample_rate: f64) -> f64 {
+        let w = std::f64::consts::TAU * frequency / sample_rate;
+        let (re, im) = shape.coefficients().iter().enumerate().fold((1.0, 0.0), |(re, im), (k, h)|
+            (re - h * (w * (k + 1) as f64).cos(), im + h * (w * (k + 1) as f64).sin()));
+        10.0 * (re * re + im * im).log10()
+    }
+
+    #[test]
+    fn noise_shaping_filters() {
+        assert!(NoiseShape::None.coefficients().is_empty());
+        assert_eq!(NoiseShape::Simple.coefficients(), &[1.0]);
+        assert_eq!(NoiseShape::FWeighted.coefficients(), &[2.412, -3.370, 3.937, -4.174, 3.353, -2.205, 1.281, -0.569, 0.0847]);
+
+        // First order error feedback is a high-pass, with no noise at DC and 6dB more at Nyquist
+        assert!(noise_gain(NoiseShape::Simple, 0.0, 44100.0) < -100.0);
+        assert!((noise_gain(NoiseShape::Simple, 22050.0, 44100.0) - 6.02).abs() < 0.01);
+
+        // The F-weighted curve is deepest where we hear best, around 3 to 4kHz, and rises past 0dB
+        // only above 12kHz
+        for (frequency, low, high) in [(0.0, -12.5, -11.5), (3500.0, -27.0, -22.0), (15000.0, 3.0, 7.0), (20000.0, 24.0, 27.0)] {
+            let gain = noise_gain(NoiseShape::FWeighted, frequency, 44100.0);
+            assert!((low .. high).contains(&gain), "{} dB at {} Hz", gain, frequency);
+        }
+        assert!((0 .. 120).map(|i| i as f64 * 100.0).all(|f| noise_gain(NoiseShape::FWeighted, f, 44100.0) < 0.0));
+    }
+
+    // With error feedback the errors cancel one another out, so a level between two steps is held
+    // on average even with no dither at all
+    #[test]
+    fn error_feedback_holds_the_level() {
+        let mut quantizer = quantizer(Dither::None, NoiseShape::Simple, 5);
+        let sum: i64 = (0 .. 10000).map(|_| quantizer.quantize(0.3 / 32767.0, 0) as i64).sum();
+        assert!((sum as f64 - 3000.0).abs() <= 1.0, "{}", sum);
+    }
+}
@@ -0,0 +1,109 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//        W H E N   T H I N G S   G O          //
+//                W R O N G                    //
+//                                             //
+/////////////////////////////////////////////////
+
+// Everything that can stop a render, each with its own exit code so scripts can tell them apart.
+// Names that aren't recognized come with the closest known name, when there's one close enough
+// to be a likely typo.
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    // A sound, window, layout, or other name that isn't known
+    UnknownName { kind: &'static str, name: String, suggestion: Option<String> },
+    // A value that can't be used, or arguments that don't agree with each other
+    InvalidParameter(String),
+    // A bit depth or sample format that can't be written
+    UnsupportedFormat(String),
+    // Reading or writing a file failed
+    Io { path: String, source: io::Error },
+    // Samples clipped, or past full scale, in strict mode
+    Clipped(u64),
+    // A file that can't be read back, or doesn't hold what was asked of it
+    Unreadable { path: String, reason: String },
+}
+
+impl Error {
+    // Look up the closest of the known names to build an unknown name error
+    pub fn unknown<'a>(kind: &'static str, name: &str, known: impl IntoIterator<Item = &'a str>) -> Error {
+        Error::UnknownName { kind, name: name.to_string(), suggestion: suggest(name, known) }
+    }
+
+    pub fn io(path: &str, source: io::Error) -> Error {
+        Error::Io { path: path.to_string(), source }
+    }
+
+    pub fn unreadable(path: &str, reason: &str) -> Error {
+        Error::Unreadable { path: path.to_string(), reason: reason.to_string() }
+    }
+
+    // The exit code for this error. 1 is left unused, 2 is clap's own usage errors, and a panic
+    // exits with 101.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::UnknownName { .. } => 3,
+            Error::InvalidParameter(_) => 4,
+            Error::UnsupportedFormat(_) => 5,
+            Error::Io { .. } => 6,
+            Error::Clipped(_) => 7,
+            Error::Unreadable { .. } => 8,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnknownName { kind, name, suggestion: Some(suggestion) } =>
+                write!(f, "Unrecognized {} \"{}\". Did you mean \"{}\"?", kind, name, suggestion),
+            Error::UnknownName { kind, name, suggestion: None } =>
+                write!(f, "Unrecognized {} \"{}\".", kind, name),
+            Error::InvalidParameter(message) => write!(f, "{}", message),
+            Error::UnsupportedFormat(message) => write!(f, "{}", message),
+            Error::Io { path, source } => write!(f, "Could not write \"{}\": {}", path, source),
+            Error::Clipped(count) => write!(f, "{} samples went past full scale, no file was written.", count),
+            Error::Unreadable { path, reason } => write!(f, "Could not read \"{}\": {}.", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// Levenshtein distance between two names, counting single character edits
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + (ca != *cb) as usize);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+// The closest known name, if it is within a third of the name's length (and at least 2 edits)
+fn suggest<'a>(name: &str, known: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let limit = (name.chars().count() / 3).max(2);
+    known.into_iter()
+        .map(|candidate| (distance(&name.to_lowercase(), &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
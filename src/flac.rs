@@ -0,0 +1,868 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//         T H E   F L A C   W R I T E R       //
+//                                             //
+/////////////////////////////////////////////////
+
+// References:
+// [13] RFC 9639, "Free Lossless Audio Codec (FLAC)"
+//  - https://www.rfc-editor.org/rfc/rfc9639
+// [14] RFC 1321, "The MD5 Message-Digest Algorithm"
+//  - https://www.rfc-editor.org/rfc/rfc1321
+
+// A small FLAC encoder of our own. Each block of 4096 frames is tried against every fixed
+// polynomial predictor of [13], and the residual of the best one is Rice coded in however many
+// partitions pay off. Stereo blocks also try the left/side, side/right, and mid/side pairings.
+// That's far from the smallest FLAC there is, but it is simple, fast, and every file decodes back
+// to exactly the samples that were rendered, which the MD5 in its STREAMINFO lets any decoder check.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use crate::wav::Spec;
+
+const BLOCK_SIZE: usize = 4096;
+const MAX_PARTITION_ORDER: u32 = 8;
+const MAX_FIXED_ORDER: usize = 4;
+
+// The channel masks FLAC assumes for 1 to 8 channels [13]. Any other layout is stored in a
+// WAVEFORMATEXTENSIBLE_CHANNEL_MASK comment, as the reference encoder does.
+const DEFAULT_MASKS: [u32; 8] = [0x4, 0x3, 0x7, 0x33, 0x37, 0x3f, 0x70f, 0x63f];
+
+// Channel assignments of the stereo pairings
+const LEFT_SIDE: u64 = 0b1000;
+const SIDE_RIGHT: u64 = 0b1001;
+const MID_SIDE: u64 = 0b1010;
+
+// Whether a spec can be written as FLAC, and why not if it can't
+pub fn supports(spec: &Spec) -> Result<(), String> {
+    if spec.sample_format != crate::wav::SampleFormat::Int || ![8, 16, 24].contains(&spec.bits_per_sample) {
+        return Err(String::from("FLAC output is 8, 16, or 24 bit int."));
+    }
+    if spec.channels > 8 {
+        return Err(format!("FLAC holds up to 8 channels, not {}.", spec.channels));
+    }
+    if spec.sample_rate >= 1 << 20 {
+        return Err(format!("FLAC sample rates go up to 1048575Hz, not {}Hz.", spec.sample_rate));
+    }
+    Ok(())
+}
+
+// Writes interleaved integer samples to a FLAC file, a block at a time. Like the WAV writer, it
+// writes to a ".part" file that is only moved into place once the STREAMINFO is filled in.
+pub struct FlacWriter {
+    file: BufWriter<File>,
+    path: String,
+    finished: bool,
+    spec: Spec,
+    block: Vec<Vec<i64>>,
+    channel: usize,
+    frames: u64,
+    blocks: u64,
+    frame_sizes: (u32, u32),
+    md5: Md5,
+}
+
+impl FlacWriter {
+    // Tags are written as Vorbis comments, each a name and its value
+    pub fn create(path: &str, spec: Spec, tags: &[(String, String)]) -> io::Result<FlacWriter> {
+        supports(&spec).map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+        let mut file = BufWriter::new(File::create(path.to_string() + ".part")?);
+        file.write_all(b"fLaC")?;
+        let mut writer = FlacWriter {
+            file,
+            path: path.to_string(),
+            finished: false,
+            spec,
+            block: vec![Vec::with_capacity(BLOCK_SIZE); spec.channels as usize],
+            channel: 0,
+            frames: 0,
+            blocks: 0,
+            frame_sizes: (u32::MAX, 0),
+            md5: Md5::new(),
+        };
+        writer.write_stream_info()?;
+
+        // The Vorbis comment block is the last metadata block, and little-endian throughout.
+        let mut comments: Vec<String> = tags.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        let mask = spec.channel_mask();
+        if spec.layout.is_some() && mask != DEFAULT_MASKS[spec.channels as usize - 1] {
+            comments.push(format!("WAVEFORMATEXTENSIBLE_CHANNEL_MASK=0x{:04X}", mask));
+        }
+        let vendor = format!("homados {}", env!("CARGO_PKG_VERSION"));
+        let mut block: Vec<u8> = Vec::new();
+        block.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        block.extend_from_slice(vendor.as_bytes());
+        block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments.iter() {
+            block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            block.extend_from_slice(comment.as_bytes());
+        }
+        writer.file.write_all(&[0x80 | 4])?;
+        writer.file.write_all(&(block.len() as u32).to_be_bytes()[1..])?;
+        writer.file.write_all(&block)?;
+        Ok(writer)
+    }
+
+    // Write one integer sample, already quantized to the bit depth
+    pub fn write_int(&mut self, sample: i32) -> io::Result<()> {
+        self.block[self.channel].push(sample as i64);
+        self.channel += 1;
+        if self.channel == self.block.len() {
+            self.channel = 0;
+            self.frames += 1;
+            if self.frames >= 1 << 36 {
+                return Err(io::Error::new(io::ErrorKind::FileTooLarge, "too many samples for a FLAC file"));
+            }
+            if self.block[0].len() == BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    // FLAC only holds integer samples
+    pub fn write_float(&mut self, _sample: f64) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "FLAC only holds integer samples"))
+    }
+
+    // Write out the last, shorter block, fill in the STREAMINFO, then move the file into place
+    pub fn finalize(mut self) -> io::Result<()> {
+        if !self.block[0].is_empty() {
+            self.write_frame()?;
+        }
+        self.file.seek(SeekFrom::Start(4))?;
+        self.write_stream_info()?;
+        self.file.flush()?;
+        fs::rename(self.path.clone() + ".part", &self.path)?;
+        self.finished = true;
+        Ok(())
+    }
+
+    // The STREAMINFO block comes first, and is written again once the stream is complete. A stream
+    // shorter than one block gives its length as the block size, but no less than the 16 [13] allows.
+    fn write_stream_info(&mut self) -> io::Result<()> {
+        let block_size = if self.frames > 0 && self.frames < BLOCK_SIZE as u64 {self.frames.max(16)} else {BLOCK_SIZE as u64};
+        let (min_frame, max_frame) = if self.blocks > 0 {self.frame_sizes} else {(0, 0)};
+        let mut out = BitWriter::new();
+        out.write(0, 1);
+        out.write(0, 7);
+        out.write(34, 24);
+        out.write(block_size, 16);
+        out.write(block_size, 16);
+        out.write(min_frame as u64, 24);
+        out.write(max_frame as u64, 24);
+        out.write(self.spec.sample_rate as u64, 20);
+        out.write(self.spec.channels as u64 - 1, 3);
+        out.write(self.spec.bits_per_sample as u64 - 1, 5);
+        out.write(self.frames >> 32, 4);
+        out.write(self.frames & 0xffffffff, 32);
+        self.file.write_all(&out.bytes)?;
+        self.file.write_all(&self.md5.clone().finish())
+    }
+
+    // Encode the buffered block as one frame. The MD5 covers the samples as signed little-endian
+    // integers, interleaved, in whole bytes.
+    fn write_frame(&mut self) -> io::Result<()> {
+        let bits = self.spec.bits_per_sample as u32;
+        let bytes_per_sample = bits.div_ceil(8) as usize;
+        let size = self.block[0].len();
+        let mut samples: Vec<u8> = Vec::with_capacity(size * self.block.len() * bytes_per_sample);
+        for i in 0 .. size {
+            for channel in self.block.iter() {
+                samples.extend_from_slice(&channel[i].to_le_bytes()[..bytes_per_sample]);
+            }
+        }
+        self.md5.update(&samples);
+
+        // Stereo blocks pick whichever pairing codes smallest. The side channel needs one more bit.
+        let (assignment, subframes) = if self.block.len() == 2 {
+            let (left, right) = (&self.block[0], &self.block[1]);
+            let side: Vec<i64> = left.iter().zip(right.iter()).map(|(l, r)| l - r).collect();
+            let mid: Vec<i64> = left.iter().zip(right.iter()).map(|(l, r)| (l + r) >> 1).collect();
+            let [left, right, side, mid] = [
+                subframe(left, bits), subframe(right, bits), subframe(&side, bits + 1), subframe(&mid, bits)];
+            [
+                (1, left.len() + right.len()),
+                (LEFT_SIDE, left.len() + side.len()),
+                (SIDE_RIGHT, side.len() + right.len()),
+                (MID_SIDE, mid.len() + side.len()),
+            ].into_iter().min_by_key(|(_, length)| *length).map(|(assignment, _)| match assignment {
+                LEFT_SIDE => (assignment, vec![left, side]),
+                SIDE_RIGHT => (assignment, vec![side, right]),
+                MID_SIDE => (assignment, vec![mid, side]),
+                _ => (assignment, vec![left, right]),
+            }).unwrap_or_default()
+        } else {
+            (self.block.len() as u64 - 1, self.block.iter().map(|channel| subframe(channel, bits)).collect())
+        };
+
+        // The frame header, with the block size and sample rate coded wherever [13] has a code
+        let mut out = BitWriter::new();
+        out.write(0b11111111111110, 14);
+        out.write(0, 1);
+        out.write(0, 1);
+        let block_size = match size {
+            BLOCK_SIZE => (0b1100, None),
+            1 ..= 256 => (0b0110, Some(8)),
+            _ => (0b0111, Some(16)),
+        };
+        out.write(block_size.0, 4);
+        let (rate_code, rate) = match self.spec.sample_rate {
+            88200 => (0b0001, None),
+            176400 => (0b0010, None),
+            192000 => (0b0011, None),
+            8000 => (0b0100, None),
+            16000 => (0b0101, None),
+            22050 => (0b0110, None),
+            24000 => (0b0111, None),
+            32000 => (0b1000, None),
+            44100 => (0b1001, None),
+            48000 => (0b1010, None),
+            96000 => (0b1011, None),
+            rate if rate < 65536 => (0b1101, Some(rate)),
+            _ => (0b0000, None),
+        };
+        out.write(rate_code, 4);
+        out.write(assignment, 4);
+        out.write(match bits {8 => 0b001, 16 => 0b100, _ => 0b110}, 3);
+        out.write(0, 1);
+        write_utf8(&mut out, self.blocks);
+        if let Some(length) = block_size.1 {out.write(size as u64 - 1, length)}
+        if let Some(rate) = rate {out.write(rate as u64, 16)}
+        let crc = crc8(&out.bytes);
+        out.write(crc as u64, 8);
+
+        for subframe in subframes.iter() {
+            out.append(subframe);
+        }
+        out.align();
+        let crc = crc16(&out.bytes);
+        out.write(crc as u64, 16);
+
+        self.file.write_all(&out.bytes)?;
+        let length = out.bytes.len() as u32;
+        self.frame_sizes = (self.frame_sizes.0.min(length), self.frame_sizes.1.max(length));
+        self.blocks += 1;
+        self.block.iter_mut().for_each(|channel| channel.clear());
+        Ok(())
+    }
+}
+
+impl Drop for FlacWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = fs::remove_file(self.path.clone() + ".part");
+        }
+    }
+}
+
+// Code one channel of a block as the smallest of a constant, verbatim, or fixed prediction subframe
+fn subframe(samples: &[i64], bits: u32) -> BitWriter {
+    let mut out = BitWriter::new();
+    if samples.iter().all(|sample| *sample == samples[0]) {
+        out.write(0b00000000, 8);
+        out.write_signed(samples[0], bits);
+        return out;
+    }
+
+    // Each order of fixed predictor leaves the differences of the order before it. The first
+    // samples of each order are its warm-up, written as they are.
+    let mut best: Option<(usize, Vec<i64>, Rice)> = None;
+    let mut residual: Vec<i64> = samples.to_vec();
+    for order in 0 ..= MAX_FIXED_ORDER.min(samples.len() - 1) {
+        if order > 0 {
+            residual = residual.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        }
+        let rice = Rice::plan(&residual, order, samples.len());
+        let length = order * bits as usize + rice.length;
+        if best.as_ref().is_none_or(|(best_order, _, best_rice)| length < *best_order * bits as usize + best_rice.length) {
+            best = Some((order, residual.clone(), rice));
+        }
+    }
+    let Some((order, residual, rice)) = best else {return out};
+    if order * bits as usize + rice.length >= samples.len() * bits as usize {
+        out.write(0b00000010, 8);
+        samples.iter().for_each(|sample| out.write_signed(*sample, bits));
+        return out;
+    }
+    out.write(0b00010000 | (order as u64) << 1, 8);
+    samples[..order].iter().for_each(|sample| out.write_signed(*sample, bits));
+    rice.write(&mut out, &residual, samples.len());
+    out
+}
+
+// How a residual is split into Rice coded partitions, and the parameter of each
+struct Rice {
+    order: u32,
+    parameters: Vec<u32>,
+    length: usize,
+}
+
+impl Rice {
+    // Find the partition order and parameters that code the residual smallest. The sums of the
+    // finest partitioning are added up pairwise for each coarser one. Costs are estimated from each
+    // partition's sum, rather than coded out in full.
+    fn plan(residual: &[i64], order: usize, block_size: usize) -> Rice {
+        let mut partition_order = MAX_PARTITION_ORDER;
+        while partition_order > 0 && (!block_size.is_multiple_of(1 << partition_order) || block_size >> partition_order <= order) {
+            partition_order -= 1;
+        }
+        let partition_size = block_size >> partition_order;
+        let mut partitions: Vec<(u64, u64)> = (0 .. 1 << partition_order).map(|i| {
+            let start = (i * partition_size).saturating_sub(order);
+            let end = (i + 1) * partition_size - order;
+            (residual[start .. end].iter().map(|r| zigzag(*r)).sum(), (end - start) as u64)
+        }).collect();
+
+        let mut best: Option<Rice> = None;
+        loop {
+            let parameters: Vec<u32> = partitions.iter().map(|(sum, count)| {
+                (0 ..= 30).min_by_key(|k| count * (k + 1) as u64 + (sum >> k)).unwrap_or(0)
+            }).collect();
+            let parameter_bits = if parameters.iter().any(|k| *k > 14) {5} else {4};
+            let length = 8 + 2 + 4 + partitions.iter().zip(parameters.iter())
+                .map(|((sum, count), k)| parameter_bits + (count * (*k as u64 + 1) + (sum >> k)) as usize)
+                .sum::<usize>();
+            if best.as_ref().is_none_or(|best| length < best.length) {
+                best = Some(Rice { order: partition_order, parameters, length });
+            }
+            if partition_order == 0 {break}
+            partition_order -= 1;
+            partitions = partitions.chunks(2).map(|pair| (pair[0].0 + pair[1].0, pair[0].1 + pair[1].1)).collect();
+        }
+        best.unwrap_or(Rice { order: 0, parameters: vec![0], length: usize::MAX })
+    }
+
+    // The first partition is short by the warm-up samples, which aren't part of the residual
+    fn write(&self, out: &mut BitWriter, residual: &[i64], block_size: usize) {
+        let wide = self.parameters.iter().any(|k| *k > 14);
+        out.write(wide as u64, 2);
+        out.write(self.order as u64, 4);
+        let mut values = residual.iter();
+        let partition_size = block_size >> self.order;
+        let warm_up = block_size - residual.len();
+        for (i, k) in self.parameters.iter().enumerate() {
+            out.write(*k as u64, if wide {5} else {4});
+            let count = if i == 0 {partition_size - warm_up} else {partition_size};
+            for value in values.by_ref().take(count) {
+                let value = zigzag(*value);
+                out.write_unary((value >> k) as usize);
+                out.write(value, *k);
+            }
+        }
+    }
+}
+
+// Fold signed values onto unsigned ones, 0, -1, 1, -2, 2, ... as 0, 1, 2, 3, 4, ...
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+// The frame number, in the extended UTF-8 coding of [13]
+fn write_utf8(out: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        out.write(value, 8);
+        return;
+    }
+    let extra = match value {
+        0 ..= 0x7ff => 1,
+        0x800 ..= 0xffff => 2,
+        0x10000 ..= 0x1fffff => 3,
+        0x200000 ..= 0x3ffffff => 4,
+        0x4000000 ..= 0x7fffffff => 5,
+        _ => 6,
+    };
+    out.write((!(0xffu64 >> (extra + 1)) & 0xff) | value >> (6 * extra), 8);
+    for i in (0 .. extra).rev() {
+        out.write(0x80 | ((value >> (6 * i)) & 0x3f), 8);
+    }
+}
+
+// CRC-8 of the frame header, polynomial x^8 + x^2 + x + 1
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, byte| {
+        (0 .. 8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 {(crc << 1) ^ 0x07} else {crc << 1})
+    })
+}
+
+// CRC-16 of the whole frame, polynomial x^16 + x^15 + x^2 + 1
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, byte| {
+        (0 .. 8).fold(crc ^ (*byte as u16) << 8, |crc, _| if crc & 0x8000 != 0 {(crc << 1) ^ 0x8005} else {crc << 1})
+    })
+}
+
+// Collects values of any width up to 32 bits, most significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { bytes: Vec::new(), buffer: 0, count: 0 }
+    }
+
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits == 0 {return}
+        self.buffer = (self.buffer << bits) | (value & ((1 << bits) - 1));
+        self.count += bits;
+        while self.count >= 8 {
+            self.count -= 8;
+            self.bytes.push((self.buffer >> self.count) as u8);
+        }
+    }
+
+    // Two's complement, in the given width
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    // The value as that many zeros, then a one
+    fn write_unary(&mut self, mut value: usize) {
+        while value >= 32 {
+            self.write(0, 32);
+            value -= 32;
+        }
+        self.write(1, value as u32 + 1);
+    }
+
+    fn append(&mut self, other: &BitWriter) {
+        other.bytes.iter().for_each(|byte| self.write(*byte as u64, 8));
+        self.write(other.buffer, other.count);
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {self.write(0, 8 - self.count)}
+    }
+
+    // Length in bits
+    fn len(&self) -> usize {
+        self.bytes.len() * 8 + self.count as usize
+    }
+}
+
+// MD5 [14], as FLAC keeps in its STREAMINFO
+#[derive(Clone)]
+struct Md5 {
+    state: [u32; 4],
+    sines: [u32; 64],
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+impl Md5 {
+    fn new() -> Md5 {
+        // The round constants are the first 32 bits of |sin(i)|, for i from 1 to 64
+        let mut sines = [0u32; 64];
+        for (i, sine) in sines.iter_mut().enumerate() {
+            *sine = ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32;
+        }
+        Md5 { state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476], sines, buffer: Vec::new(), length: 0 }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.length += bytes.len() as u64;
+        self.buffer.extend_from_slice(bytes);
+        let blocks = self.buffer.len() / 64;
+        for i in 0 .. blocks {
+            let mut block = [0u8; 64];
+            block.copy_from_slice(&self.buffer[i * 64 .. (i + 1) * 64]);
+            self.compress(&block);
+        }
+        self.buffer.drain(.. blocks * 64);
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+        let bits = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80u8];
+        padding.resize(((119 - self.length % 64) % 64) as usize + 1, 0);
+        padding.extend_from_slice(&bits.to_le_bytes());
+        let length = self.length;
+        self.update(&padding);
+        self.length = length;
+        let mut digest = [0u8; 16];
+        for (i, word) in self.state.iter().enumerate() {
+            digest[i * 4 .. (i + 1) * 4].copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        let words: Vec<u32> = block.chunks(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect();
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0 .. 64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(self.sines[i]).wrapping_add(words[g])
+                .rotate_left(MD5_SHIFTS[(i / 16) * 4 + i % 4]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Metadata;
+    use crate::wav::{Container, SampleFormat, WavWriter};
+
+    fn md5(bytes: &[u8]) -> String {
+        let mut md5 = Md5::new();
+        md5.update(bytes);
+        md5.finish().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    // The test suite of [14], appendix A.5
+    #[test]
+    fn md5_test_suite() {
+        assert_eq!(md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5(b"a"), "0cc175b9c0f1b6a831c399e269772661");
+        assert_eq!(md5(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(md5(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+        assert_eq!(md5(b"abcdefghijklmnopqrstuvwxyz"), "c3fcd3d76192e4007dfb496cca67e13b");
+        assert_eq!(md5(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"),
+            "d174ab98d277d9f5a5611c2c9f419d9f");
+        assert_eq!(md5("1234567890".repeat(8).as_bytes()), "57edf4a22be3c955ac49da2e2107b67a");
+    }
+
+    // Past 55 bytes the length no longer fits in the last block, and the padding spills into another
+    #[test]
+    fn md5_padding_lengths() {
+        assert_eq!(md5(&[b'a'; 55]), "ef1772b6dff9a122358552954ad0df65");
+        assert_eq!(md5(&[b'a'; 56]), "3b0c8ac703f828b04c6c197006d17218");
+        assert_eq!(md5(&[b'a'; 63]), "b06521f39153d618550606be297466d5");
+        assert_eq!(md5(&[b'a'; 64]), "014842d480b571495a4a0363793f7367");
+    }
+
+    // Bytes given a piece at a time hash the same as all at once
+    #[test]
+    fn md5_in_pieces() {
+        let bytes: Vec<u8> = (0 .. 200u32).map(|i| (i * 7 + 3) as u8).collect();
+        let mut pieces = Md5::new();
+        for piece in [&bytes[..1], &bytes[1..64], &bytes[64..120], &bytes[120..]] {
+            pieces.update(piece);
+        }
+        assert_eq!(pieces.finish(), { let mut whole = Md5::new(); whole.update(&bytes); whole.finish() });
+    }
+
+    // The decoder below checks frames with the encoder's own CRCs, so those are pinned to the
+    // standard check values of CRC-8 (poly 0x07) and CRC-16/UMTS (poly 0x8005)
+    #[test]
+    fn crc_check_values() {
+        assert_eq!(crc8(b"123456789"), 0xf4);
+        assert_eq!(crc16(b"123456789"), 0xfee8);
+    }
+
+    // Reads values of any width, most significant bit first, as BitWriter writes them
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        at: usize,
+    }
+
+    impl BitReader<'_> {
+        fn read(&mut self, bits: u32) -> u64 {
+            (0 .. bits).fold(0, |value, _| {
+                let bit = (self.bytes[self.at / 8] >> (7 - self.at % 8)) & 1;
+                self.at += 1;
+                (value << 1) | bit as u64
+            })
+        }
+
+        fn read_signed(&mut self, bits: u32) -> i64 {
+            let value = self.read(bits) as i64;
+            if bits > 0 && value >> (bits - 1) != 0 {value - (1 << bits)} else {value}
+        }
+
+        fn read_unary(&mut self) -> u64 {
+            let mut value = 0;
+            while self.read(1) == 0 {value += 1}
+            value
+        }
+
+        fn align(&mut self) {
+            self.at = self.at.div_ceil(8) * 8;
+        }
+    }
+
+    // What a decoded file holds: its STREAMINFO, the samples of each channel, and the channel
+    // assignment of every frame
+    struct Decoded {
+        sample_rate: u32,
+        channels: usize,
+        bits: u32,
+        frames: u64,
+        md5: [u8; 16],
+        samples: Vec<Vec<i64>>,
+        assignments: Vec<u64>,
+    }
+
+    // Decode the subset of [13] the encoder writes, checking the CRC of every frame and frame header,
+    // and the smallest and largest frame sizes in the STREAMINFO
+    fn decode(file: &[u8]) -> Decoded {
+        assert_eq!(&file[..4], b"fLaC");
+        let mut at = 4;
+        let mut stream_info: Option<&[u8]> = None;
+        loop {
+            let (last, kind) = (file[at] & 0x80 != 0, file[at] & 0x7f);
+            let length = u32::from_be_bytes([0, file[at + 1], file[at + 2], file[at + 3]]) as usize;
+            if kind == 0 {stream_info = Some(&file[at + 4 .. at + 4 + length])}
+            at += 4 + length;
+            if last {break}
+        }
+        let mut info = BitReader { bytes: stream_info.expect("no STREAMINFO"), at: 0 };
+        let (_min_block, _max_block) = (info.read(16), info.read(16));
+        let frame_sizes = (info.read(24) as usize, info.read(24) as usize);
+        let sample_rate = info.read(20) as u32;
+        let channels = info.read(3) as usize + 1;
+        let bits = info.read(5) as u32 + 1;
+        let frames = info.read(36);
+        let mut md5 = [0u8; 16];
+        md5.iter_mut().for_each(|byte| *byte = info.read(8) as u8);
+
+        let mut decoded = Decoded { sample_rate, channels, bits, frames, md5,
+            samples: vec![Vec::new(); channels], assignments: Vec::new() };
+        let mut measured_sizes = (usize::MAX, 0);
+        let mut input = BitReader { bytes: file, at: at * 8 };
+        while input.at / 8 < file.len() {
+            let start = input.at / 8;
+            assert_eq!(input.read(14), 0b11111111111110, "lost sync");
+            assert_eq!(input.read(2), 0);
+            let (size_code, rate_code) = (input.read(4), input.read(4));
+            let assignment = input.read(4);
+            assert_eq!(input.read(3), match bits {8 => 0b001, 16 => 0b100, _ => 0b110});
+            assert_eq!(input.read(1), 0);
+            let first = input.read(8);
+            for _ in 0 .. (first as u8).leading_ones().saturating_sub(1) {
+                assert_eq!(input.read(8) & 0xc0, 0x80);
+            }
+            let size = match size_code {
+                0b0001 => 192,
+                0b0010 ..= 0b0101 => 576 << (size_code - 2),
+                0b0110 => input.read(8) as usize + 1,
+                0b0111 => input.read(16) as usize + 1,
+                _ => 256 << (size_code - 8),
+            };
+            match rate_code {
+                0b1100 => assert_eq!(input.read(8) as u32 * 1000, sample_rate),
+                0b1101 => assert_eq!(input.read(16) as u32, sample_rate),
+                0b1110 => assert_eq!(input.read(16) as u32 * 10, sample_rate),
+                _ => {}
+            }
+            let header_crc = crc8(&file[start .. input.at / 8]);
+            assert_eq!(input.read(8) as u8, header_crc, "frame header CRC-8");
+
+            let mut block: Vec<Vec<i64>> = (0 .. channels).map(|channel| {
+                let side = matches!((assignment, channel), (LEFT_SIDE, 1) | (SIDE_RIGHT, 0) | (MID_SIDE, 1));
+                decode_subframe(&mut input, size, bits + side as u32)
+            }).collect();
+            match assignment {
+                LEFT_SIDE => block[1] = block[0].iter().zip(&block[1]).map(|(left, side)| left - side).collect(),
+                SIDE_RIGHT => block[0] = block[0].iter().zip(&block[1]).map(|(side, right)| side + right).collect(),
+                MID_SIDE => {
+                    let (mid, side) = (block[0].clone(), block[1].clone());
+                    let whole: Vec<i64> = mid.iter().zip(&side).map(|(mid, side)| (mid << 1) | (side & 1)).collect();
+                    block[0] = whole.iter().zip(&side).map(|(mid, side)| (mid + side) >> 1).collect();
+                    block[1] = whole.iter().zip(&side).map(|(mid, side)| (mid - side) >> 1).collect();
+                }
+                _ => assert_eq!(assignment as usize, channels - 1),
+            }
+            input.align();
+            let frame_crc = crc16(&file[start .. input.at / 8]);
+            assert_eq!(input.read(16) as u16, frame_crc, "frame CRC-16");
+
+            let length = input.at / 8 - start;
+            measured_sizes = (measured_sizes.0.min(length), measured_sizes.1.max(length));
+            decoded.samples.iter_mut().zip(block).for_each(|(samples, block)| samples.extend(block));
+            decoded.assignments.push(assignment);
+        }
+        assert_eq!(frame_sizes, measured_sizes, "STREAMINFO frame sizes");
+        decoded
+    }
+
+    // Constant, verbatim, and fixed prediction subframes, with their Rice coded residuals
+    fn decode_subframe(input: &mut BitReader, size: usize, bits: u32) -> Vec<i64> {
+        assert_eq!(input.read(1), 0);
+        let kind = input.read(6);
+        assert_eq!(input.read(1), 0, "no wasted bits are written");
+        match kind {
+            0 => vec![input.read_signed(bits); size],
+            1 => (0 .. size).map(|_| input.read_signed(bits)).collect(),
+            8 ..= 12 => {
+                let order = kind as usize - 8;
+                let mut samples: Vec<i64> = (0 .. order).map(|_| input.read_signed(bits)).collect();
+                let wide = input.read(2) == 1;
+                let partition_order = input.read(4);
+                let partition_size = size >> partition_order;
+                let mut residual: Vec<i64> = Vec::new();
+                for partition in 0 .. 1 << partition_order {
+                    let k = input.read(if wide {5} else {4}) as u32;
+                    let count = if partition == 0 {partition_size - order} else {partition_size};
+                    for _ in 0 .. count {
+                        let value = (input.read_unary() << k) | input.read(k);
+                        residual.push((value >> 1) as i64 ^ -((value & 1) as i64));
+                    }
+                }
+                for value in residual {
+                    let s = |back: usize| samples[samples.len() - back];
+                    let prediction = match order {
+                        0 => 0,
+                        1 => s(1),
+                        2 => 2 * s(1) - s(2),
+                        3 => 3 * s(1) - 3 * s(2) + s(3),
+                        _ => 4 * s(1) - 6 * s(2) + 4 * s(3) - s(4),
+                    };
+                    samples.push(prediction + value);
+                }
+                samples
+            }
+            _ => panic!("unexpected subframe type {}", kind),
+        }
+    }
+
+    // Write the same samples as a FLAC and a WAV, then check the FLAC decodes to the WAV's data
+    // chunk, with every CRC and the STREAMINFO in order. Returns the channel assignments used.
+    fn round_trip(name: &str, bits: u16, channels: Vec<Vec<i64>>) -> Vec<u64> {
+        let spec = Spec { channels: channels.len() as u16, sample_rate: 44100, bits_per_sample: bits,
+            sample_format: SampleFormat::Int, layout: None };
+        let path = std::env::temp_dir().join(format!("homados-test-{}-{}", std::process::id(), name));
+        let (flac_path, wav_path) = (path.with_extension("flac"), path.with_extension("wav"));
+        let mut flac = FlacWriter::create(flac_path.to_str().unwrap(), spec, &[]).unwrap();
+        let mut wav = WavWriter::create(wav_path.to_str().unwrap(), spec, Container::Riff, &Metadata::default()).unwrap();
+        for i in 0 .. channels[0].len() {
+            for channel in channels.iter() {
+                flac.write_int(channel[i] as i32).unwrap();
+                wav.write_int(channel[i] as i32).unwrap();
+            }
+        }
+        flac.finalize().unwrap();
+        wav.finalize().unwrap();
+        let (flac, wav) = (fs::read(&flac_path).unwrap(), fs::read(&wav_path).unwrap());
+        let _ = (fs::remove_file(&flac_path), fs::remove_file(&wav_path));
+
+        let decoded = decode(&flac);
+        assert_eq!((decoded.sample_rate, decoded.channels, decoded.bits), (44100, channels.len(), bits as u32));
+        assert_eq!(decoded.frames, channels[0].len() as u64);
+        assert_eq!(decoded.samples, channels);
+
+        let data = wav.windows(4).position(|id| id == b"data").unwrap() + 8;
+        let bytes_per_sample = (bits as usize).div_ceil(8);
+        let samples: Vec<u8> = (0 .. channels[0].len())
+            .flat_map(|i| decoded.samples.iter().flat_map(move |channel| channel[i].to_le_bytes()[..bytes_per_sample].to_vec()))
+            .collect();
+        assert_eq!(samples, wav[data ..]);
+        let mut md5 = Md5::new();
+        md5.update(&samples);
+        assert_eq!(decoded.md5, md5.finish(), "STREAMINFO MD5");
+        decoded.assignments
+    }
+
+    // A tone with a little noise on it, so every kind of subframe gets a turn. Noise is drawn from
+    // a fixed LCG, so every run tests the same samples.
+    fn signal(frames: usize, bits: u16, frequency: f64, noise: i64, seed: u64) -> Vec<i64> {
+        let full_scale = ((1i64 << (bits - 1)) - 1) as f64;
+        let mut state = seed;
+        (0 .. frames).map(|i| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let dither = ((state >> 33) as i64 % (2 * noise + 1)) - noise;
+            let tone = (std::f64::consts::TAU * frequency * i as f64 / 44100.0).sin() * full_scale * 0.5;
+            (tone as i64 + dither).clamp(-(full_scale as i64) - 1, full_scale as i64)
+        }).collect()
+    }
+
+    #[test]
+    fn round_trip_mono_16_bit() {
+        // Two full blocks, then one shorter than 256 frames, whose size is coded in 8 bits
+        round_trip("mono16", 16, vec![signal(2 * BLOCK_SIZE + 100, 16, 997.0, 4, 1)]);
+    }
+
+    #[test]
+    fn round_trip_mono_24_bit() {
+        // A last block longer than 256 frames, whose size is coded in 16 bits
+        round_trip("mono24", 24, vec![signal(BLOCK_SIZE + 1000, 24, 440.0, 64, 2)]);
+    }
+
+    #[test]
+    fn round_trip_silence_and_full_scale() {
+        let mut samples = vec![0; BLOCK_SIZE];
+        samples.extend((0 .. 3000).map(|i| if i % 2 == 0 {32767} else {-32768}));
+        round_trip("extremes16", 16, vec![samples]);
+    }
+
+    #[test]
+    fn round_trip_stereo_16_bit() {
+        // Nearly identical channels code smallest as a pairing with a side channel
+        let left = signal(3 * BLOCK_SIZE + 7, 16, 1000.0, 8, 3);
+        let right: Vec<i64> = left.iter().zip(signal(left.len(), 16, 50.0, 2, 4)).map(|(l, r)| l + r / 200).collect();
+        let assignments = round_trip("stereo16", 16, vec![left, right]);
+        assert!(assignments.iter().any(|assignment| [LEFT_SIDE, SIDE_RIGHT, MID_SIDE].contains(assignment)));
+    }
+
+    #[test]
+    fn round_trip_stereo_24_bit() {
+        // Unrelated channels, where some blocks stay left/right
+        let left = signal(2 * BLOCK_SIZE + 300, 24, 440.0, 1000, 5);
+        let right = signal(left.len(), 24, 1234.5, 1000, 6);
+        round_trip("stereo24", 24, vec![left, right]);
+    }
+    // The whole file for the samples 0 to 7, mono 16 bit 44.1kHz, worked out by hand from [13]. The
+    // ramp is coded as a first order fixed predictor, leaving a residual of seven 1s, which costs
+    // 21 bits as Rice codes with a parameter of 0. The CRCs and MD5 were worked out apart from the
+    // encoder too.
+    #[test]
+    fn known_answer() {
+        let hex = |hex: &[&str]| -> Vec<u8> {
+            hex.concat().as_bytes().chunks(2).map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap()).collect()
+        };
+        let vendor = format!("homados {}", env!("CARGO_PKG_VERSION"));
+        let expected: Vec<u8> = [
+            hex(&[
+                // "fLaC", then the STREAMINFO header: not the last block, type 0, 34 bytes long
+                "664c6143", "00000022",
+                // Block sizes of 16 at least, frame sizes of 16 bytes, then 44100Hz, 1 channel, 16
+                // bits, and 8 samples in all, in 20, 3, 5, and 36 bits
+                "0010", "0010", "000010", "000010", "0ac440f000000008",
+                // The MD5 of the samples as 16 bit little-endian integers
+                "d63087a58b641238ecddb0670cba1940",
+                // The Vorbis comment block header: the last block, type 4, and its length
+                "84", "0000", &format!("{:02x}", 4 + vendor.len() + 4),
+            ]),
+            // Its vendor string, and no comments
+            (vendor.len() as u32).to_le_bytes().to_vec(), vendor.into_bytes(), vec![0; 4],
+            hex(&[
+                // Frame header: sync code and fixed block size, an 8 bit block size with 44.1kHz,
+                // mono and 16 bits, frame 0, a block of 8, and the CRC-8
+                "fff8", "69", "08", "00", "07", "08",
+                // Subframe header: fixed predictor of order 1, no wasted bits, and warm-up sample 0
+                "12", "0000",
+                // Rice coding with 4 bit parameters, partition order 0, parameter 0, then 7 times
+                // "001" and a bit of padding: 00 0000 0000 001001001001001001001 0
+                "00092492",
+                // The CRC-16 of the frame
+                "097c",
+            ]),
+        ].concat();
+
+        let spec = Spec { channels: 1, sample_rate: 44100, bits_per_sample: 16, sample_format: SampleFormat::Int, layout: None };
+        let path = std::env::temp_dir().join(format!("homados-test-{}-known-answer.flac", std::process::id()));
+        let mut flac = FlacWriter::create(path.to_str().unwrap(), spec, &[]).unwrap();
+        for sample in 0 .. 8 {
+            flac.write_int(sample).unwrap();
+        }
+        flac.finalize().unwrap();
+        let written = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(written, expected);
+    }
+}
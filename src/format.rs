@@ -0,0 +1,311 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//        O U T P U T   F O R M A T S          //
+//                                             //
+/////////////////////////////////////////////////
+
+// Every file format a render can be written as, and the one Writer trait they are all written
+// through. A render only ever sees interleaved samples going into a Writer, so each format can
+// lay them out however its files need, or stream them to another program as they come.
+
+use std::io::{self, Write};
+use crate::aiff::{self, AiffWriter};
+use crate::flac::{self, FlacWriter};
+use crate::wav::{Container, SampleFormat, Spec, StreamWriter, WavWriter};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Wav,
+    Rf64,
+    Wave64,
+    Flac,
+    Aiff,
+    Raw,
+}
+
+// All format names and aliases. The first name of each entry is its canonical name, and also the
+// extension its files are given, except for RF64, which keeps the ".wav" of the WAV it extends.
+pub const FORMATS: &[(Format, &[&str])] = &[
+    (Format::Wav, &["wav", "wave"]),
+    (Format::Rf64, &["rf64"]),
+    (Format::Wave64, &["w64", "wave64"]),
+    (Format::Flac, &["flac"]),
+    (Format::Aiff, &["aiff", "aif", "aifc", "aiff-c"]),
+    (Format::Raw, &["raw", "pcm", "u8", "s16le", "s24le", "s32le", "f32le", "f64le"]),
+];
+
+// Raw PCM has no header to say what it holds, so its names give the sample format and bit depth.
+// Samples are interleaved and little-endian, with 8 bit samples unsigned as they are in WAV.
+pub const RAW_ENCODINGS: &[(&str, SampleFormat, u16)] = &[
+    ("u8", SampleFormat::Int, 8),
+    ("s16le", SampleFormat::Int, 16),
+    ("s24le", SampleFormat::Int, 24),
+    ("s32le", SampleFormat::Int, 32),
+    ("f32le", SampleFormat::Float, 32),
+    ("f64le", SampleFormat::Float, 64),
+];
+
+impl Format {
+    // Look up a format by any of its names
+    pub fn from_name(name: &str) -> Option<Format> {
+        let name = name.to_lowercase();
+        FORMATS.iter().find(|(_, names)| names.contains(&name.as_str())).map(|(format, _)| *format)
+    }
+
+    // The canonical name of this format
+    pub fn name(self) -> &'static str {
+        FORMATS.iter().find(|(format, _)| *format == self).map(|(_, names)| names[0]).unwrap_or("")
+    }
+
+    // The extension of this format's files, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Rf64 => Format::Wav.name(),
+            _ => self.name(),
+        }
+    }
+
+    // The container of the formats written as WAV
+    pub fn container(self) -> Option<Container> {
+        match self {
+            Format::Wav => Some(Container::Riff),
+            Format::Rf64 => Some(Container::Rf64),
+            Format::Wave64 => Some(Container::Wave64),
+            _ => None,
+        }
+    }
+
+    // Whether this format can be streamed, without going back to fill anything in once it's done
+    pub fn streams(self) -> bool {
+        self.container().is_some() || self == Format::Raw
+    }
+
+    // The sample format and bit depth a raw PCM name stands for, like "s24le"
+    pub fn raw_encoding(name: &str) -> Option<(SampleFormat, u16)> {
+        let name = name.to_lowercase();
+        RAW_ENCODINGS.iter().find(|(encoding, _, _)| *encoding == name).map(|(_, format, bits)| (*format, *bits))
+    }
+
+    // The format a file name asks for with its extension, along with the name without it
+    pub fn from_file_name(name: &str) -> Option<(Format, &str)> {
+        let (stem, extension) = name.rsplit_once('.')?;
+        Format::from_name(extension).map(|format| (format, stem))
+    }
+
+    // Whether a spec can be written in this format, and why not if it can't
+    pub fn supports(self, spec: &Spec) -> Result<(), String> {
+        match self {
+            Format::Wav | Format::Rf64 | Format::Wave64 | Format::Aiff | Format::Raw => Ok(()),
+            Format::Flac => flac::supports(spec),
+        }
+    }
+
+    // The format a render of this many frames is written in, when the length is known. A WAV too
+    // long for the 32 bit sizes of RIFF is written as RF64 instead, and a render too long for any
+    // other format is an error.
+    pub fn for_length(self, spec: &Spec, metadata: &Metadata, frames: Option<u64>) -> Result<Format, String> {
+        let Some(frames) = frames else {return Ok(self)};
+        match self {
+            Format::Wav if !Container::Riff.holds(spec, metadata, frames) => Ok(Format::Rf64),
+            Format::Aiff if !aiff::holds(spec, frames) =>
+                Err(String::from("The render is too long for an AIFF file, use wav, w64, or flac.")),
+            Format::Flac if frames >= 1 << 36 =>
+                Err(String::from("The render is too long for a FLAC file, use wav or w64.")),
+            _ => Ok(self),
+        }
+    }
+}
+
+// A loop over whole cycles of a periodic sound, from its first frame up to, but not including, its
+// end frame. It plays back at the sound's frequency.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Loop {
+    pub start: u32,
+    pub end: u32,
+    pub frequency: f64,
+}
+
+impl Loop {
+    // The longest loop of whole cycles in a render, with as little rounding as can be. It starts on
+    // the frame nearest the first zero crossing, given as the phase of the cycle it falls on, so it
+    // also ends on one. A cycle rarely lasts a whole number of frames, so of the cycle counts that
+    // fit, the one that ends closest to a whole frame is taken, the longest of those if several
+    // tie. Only the longest million or so counts are looked at, which always holds an exact one for
+    // any whole frequency. Loop points are 32 bit wherever they're kept, so only the first 2^32
+    // frames count.
+    pub fn whole_cycles(frequency: f64, sample_rate: u32, frames: u64, zero_crossing: f64) -> Option<Loop> {
+        let frames = frames.min(u32::MAX as u64) as u32;
+        let period = sample_rate as f64 / frequency;
+        if !period.is_finite() || period <= 0.0 {return None}
+        let start = (zero_crossing.rem_euclid(1.0) * period).round() as u32;
+        let cycles = (frames.saturating_sub(start) as f64 / period).floor() as u32;
+        let best = (cycles.saturating_sub(1 << 20).max(1) ..= cycles).map(|count| (count, count as f64 * period))
+            .min_by(|(a, a_len), (b, b_len)| (a_len - a_len.round()).abs().total_cmp(&(b_len - b_len.round()).abs())
+                .then(b.cmp(a)))?;
+        Some(Loop { start, end: (start + best.1.round() as u32).min(frames), frequency })
+    }
+
+    // The nearest MIDI note to the loop's frequency, and how many cents it is off from it
+    pub fn midi_note(&self) -> (u8, i8) {
+        let note = 69.0 + 12.0 * (self.frequency / 440.0).log2();
+        let nearest = note.round().clamp(0.0, 127.0);
+        (nearest as u8, ((note - nearest) * 100.0).round().clamp(-50.0, 50.0) as i8)
+    }
+}
+
+// A labelled point in a render, such as where a sweep passes 1 kHz. Like loop points, marker
+// positions are 32 bit wherever they're kept.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Marker {
+    pub position: u32,
+    pub label: String,
+}
+
+// The sets of points a render can be marked at, besides a list of values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkerSet {
+    Octave,
+    Decade,
+    Window,
+}
+
+// All marker set names and aliases. The first name of each entry is its canonical name.
+pub const MARKER_SETS: &[(MarkerSet, &[&str])] = &[
+    (MarkerSet::Octave, &["octave", "octaves"]),
+    (MarkerSet::Decade, &["decade", "decades"]),
+    (MarkerSet::Window, &["window", "windows", "fade", "fades"]),
+];
+
+impl MarkerSet {
+    // Look up a marker set by any of its names
+    pub fn from_name(name: &str) -> Option<MarkerSet> {
+        let name = name.to_lowercase();
+        MARKER_SETS.iter().find(|(_, names)| names.contains(&name.as_str())).map(|(set, _)| *set)
+    }
+
+    // The frequencies of this set from low to high, in octaves either side of 1 kHz as the octave
+    // bands of ISO 266 are, or in powers of ten. Windows aren't marked by frequency.
+    pub fn frequencies(self, low: f64, high: f64) -> Vec<f64> {
+        let (base, step): (f64, f64) = match self {
+            MarkerSet::Octave => (1000.0, 2.0),
+            MarkerSet::Decade => (1.0, 10.0),
+            MarkerSet::Window => return Vec::new(),
+        };
+        if !(low > 0.0 && high >= low && high.is_finite()) {return Vec::new()}
+        // Logarithms of exact powers can come out a hair off, so the ends are given some leeway
+        let first = ((low / base).log(step) - 1e-9).ceil() as i32;
+        let last = ((high / base).log(step) + 1e-9).floor() as i32;
+        (first ..= last).map(|power| base * step.powi(power)).collect()
+    }
+}
+
+// Everything a file can hold about a render besides its samples. Each format keeps as much of it as
+// it has room for.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    // Names and values describing the render
+    pub tags: Vec<(String, String)>,
+    // A loop over whole cycles, for periodic sounds
+    pub looped: Option<Loop>,
+    // Where the render starts on a timeline, in frames since midnight
+    pub time_reference: u64,
+    // Labelled points in the render, in order
+    pub markers: Vec<Marker>,
+}
+
+impl Metadata {
+    // The value of a tag, by its name
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+}
+
+// Takes interleaved samples, one frame after another, and finalizes the file once they're all in.
+// A writer that's dropped without being finalized leaves nothing behind.
+pub trait Writer {
+    // Write one integer sample, already quantized to the bit depth
+    fn write_int(&mut self, sample: i32) -> io::Result<()>;
+
+    // Write one float sample, where 1.0 is full scale
+    fn write_float(&mut self, sample: f64) -> io::Result<()>;
+
+    // Finish the file and move it into place
+    fn finalize(self: Box<Self>) -> io::Result<()>;
+}
+
+impl Writer for WavWriter {
+    fn write_int(&mut self, sample: i32) -> io::Result<()> {
+        WavWriter::write_int(self, sample)
+    }
+
+    fn write_float(&mut self, sample: f64) -> io::Result<()> {
+        WavWriter::write_float(self, sample)
+    }
+
+    fn finalize(self: Box<Self>) -> io::Result<()> {
+        WavWriter::finalize(*self)
+    }
+}
+
+impl Writer for AiffWriter {
+    fn write_int(&mut self, sample: i32) -> io::Result<()> {
+        AiffWriter::write_int(self, sample)
+    }
+
+    fn write_float(&mut self, sample: f64) -> io::Result<()> {
+        AiffWriter::write_float(self, sample)
+    }
+
+    fn finalize(self: Box<Self>) -> io::Result<()> {
+        AiffWriter::finalize(*self)
+    }
+}
+
+impl Writer for StreamWriter {
+    fn write_int(&mut self, sample: i32) -> io::Result<()> {
+        StreamWriter::write_int(self, sample)
+    }
+
+    fn write_float(&mut self, sample: f64) -> io::Result<()> {
+        StreamWriter::write_float(self, sample)
+    }
+
+    fn finalize(self: Box<Self>) -> io::Result<()> {
+        StreamWriter::finalize(*self)
+    }
+}
+
+impl Writer for FlacWriter {
+    fn write_int(&mut self, sample: i32) -> io::Result<()> {
+        FlacWriter::write_int(self, sample)
+    }
+
+    fn write_float(&mut self, sample: f64) -> io::Result<()> {
+        FlacWriter::write_float(self, sample)
+    }
+
+    fn finalize(self: Box<Self>) -> io::Result<()> {
+        FlacWriter::finalize(*self)
+    }
+}
+
+// Create a writer for the given format, holding whatever of the metadata it can
+pub fn create_writer(format: Format, path: &str, spec: Spec, metadata: &Metadata) -> io::Result<Box<dyn Writer>> {
+    Ok(match format {
+        Format::Wav | Format::Rf64 | Format::Wave64 =>
+            Box::new(WavWriter::create(path, spec, format.container().unwrap_or(Container::Riff), metadata)?),
+        Format::Flac => Box::new(FlacWriter::create(path, spec, &metadata.tags)?),
+        Format::Aiff => Box::new(AiffWriter::create(path, spec, metadata.looped)?),
+        Format::Raw => return Err(io::Error::new(io::ErrorKind::InvalidInput, "raw PCM is only streamed")),
+    })
+}
+
+// Create a writer streaming to another program, for the formats that stream. The length in frames
+// goes in the header when it's known.
+pub fn create_stream(format: Format, out: Box<dyn Write>, spec: Spec, frames: Option<u64>, metadata: &Metadata) -> io::Result<Box<dyn Writer>> {
+    match format {
+        _ if format.streams() => Ok(Box::new(StreamWriter::create(out, spec, format.container(), frames, metadata)?)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} can't be streamed", format.name()))),
+    }
+}
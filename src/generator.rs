@@ -9,45 +9,532 @@
 // References:
 // [1] The Paul Kellet filtering methods used for our pink (and blue) noise were retrieved from here:
 //  - https://www.firstpr.com.au/dsp/pink-noise/
+// [3] The PolyBLEP and PolyBLAMP residuals used for our band-limited waveshapes are described here:
+//  - V. Valimaki, J. Pekonen, J. Nam, "Perceptually informed synthesis of bandlimited classical
+//      waveforms using integrated polynomial interpolation", JASA 131(1), 2012
+//  - F. Esqueda, V. Valimaki, S. Bilbao, "Rounding Corners with BLAMP", DAFx-16, 2016
+// [4] The closed-form band-limited impulse train used for our Dirac combs is described here:
+//  - T. Stilson, J. Smith, "Alias-Free Digital Synthesis of Classic Analog Waveforms", ICMC 1996
 
-use std::{f64::consts::{TAU}, path::Path};
+use std::{f64::consts::{PI, TAU}, io, path::Path};
 use rand::prelude::*;
-use rand_distr::{Bernoulli, Normal, Triangular};
-use hound;
-use crate::window;
+use rand_distr::{Bernoulli, StandardNormal, Triangular};
+use crate::dither::Quantizer;
+use crate::error::Error;
+use crate::format::{self, Format, Metadata};
+use crate::normalize::{Meter, Normalize};
+use crate::peak::{Limiter, TruePeak};
+use crate::wav;
+use crate::window::Envelope;
 
-// Generic container to store information about our sound type
+// Every sound type the generator knows about. Names are resolved to one of these once, up front,
+// so rendering never has to look at strings again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundType {
+    Silence, Dc,
+    Sine, Cosine, SweepLinSine, SweepExpSine, ClipSine, QuantSine,
+    Saw, Square, Triangle, Pulse, PulseSweepWidth, Sharktooth,
+    SawBl, SquareBl, TriangleBl, PulseBl, SharktoothBl,
+    UnitImpulse, DiracComb, DiracCombNaive, SweepLinComb, SweepExpComb,
+    Random, White, WhiteNormal, WhiteTri, WhiteBin,
+    PinkKelletEcon, PinkKelletRef, Brown, BluePke, BluePk3, Violet, PseudoVelvet,
+}
+
+// All sound type names and aliases. The first name of each entry is its canonical name.
+pub const SOUND_TYPES: &[(SoundType, &[&str])] = &[
+    (SoundType::Silence, &["silence", "silent", "zero", "null"]),
+    (SoundType::Dc, &["dc", "dc_offset", "offset", "constant", "const"]),
+    (SoundType::Sine, &["sine", "sin", "sine_wave", "sinusoid"]),
+    (SoundType::Cosine, &["cosine", "cos", "cosine_wave"]),
+    (SoundType::SweepLinSine, &["sweep_lin_sin", "sweep_lin", "sweep_linear", "chirp_lin", 
+        "chirp_linear", "lss"]),
+    (SoundType::SweepExpSine, &["sweep_exp_sin", "sweep", "sweep_log", "sweep_sin", 
+        "sweep_log_sin", "chirp", "chirp_log", "chirp_exp", "ess", "sweep_exp", "log_sweep", 
+        "exp_sweep"]),
+    (SoundType::ClipSine, &["clip_sine", "clip_sin", "clipped_sine", "hardclip_sine", 
+        "hard_clip_sine", "hardclipped_sine", "hard_clipped_sine"]),
+    (SoundType::QuantSine, &["quant_sine", "quant_sin", "quantized_sine", "quantized_sin"]),
+    (SoundType::Saw, &["saw", "sawtooth", "saw_wave"]),
+    (SoundType::Square, &["square", "sqr", "square_wave"]),
+    (SoundType::Triangle, &["triangle", "tri", "triangle_wave"]),
+    (SoundType::Pulse, &["pulse", "pwm", "pw", "pulse_wave"]),
+    (SoundType::PulseSweepWidth, &["pulse_width_sweep", "pwm_sweep", "pw_sweep"]),
+    (SoundType::Sharktooth, &["sharktooth", "shark", "sharktooth_wave"]),
+    (SoundType::SawBl, &["saw_bl", "sawtooth_bl", "saw_blep", "saw_polyblep"]),
+    (SoundType::SquareBl, &["square_bl", "sqr_bl", "sqr_blep", "square_blep", "square_polyblep"]),
+    (SoundType::TriangleBl, &["triangle_bl", "tri_bl", "tri_blamp", "triangle_blamp", 
+        "triangle_polyblamp"]),
+    (SoundType::PulseBl, &["pulse_bl", "pwm_bl", "pw_bl", "pulse_blep", "pulse_polyblep"]),
+    (SoundType::SharktoothBl, &["sharktooth_bl", "shark_bl", "shark_blep", "sharktooth_polyblep"]),
+    (SoundType::UnitImpulse, &["unit_impulse", "dirac", "delta", "kronecker", "dirac_delta", 
+        "kronecker_delta", "click"]),
+    (SoundType::DiracComb, &["dirac_comb", "impulse_train", "needle", "comb", "needle_pulse", 
+        "sha", "blit"]),
+    (SoundType::DiracCombNaive, &["dirac_comb_naive", "comb_naive", "impulse_train_naive", 
+        "needle_naive"]),
+    (SoundType::SweepLinComb, &["sweep_lin_comb", "comb_lin", "dirac_comb_lin", 
+        "linear_comb_sweep", "needle_lin", "needle_sweep_lin"]),
+    (SoundType::SweepExpComb, &["sweep_exp_comb", "comb_exp", "comb_sweep", "sweep_comb", 
+        "dirac_comb_exp", "exp_comb_sweep", "needle_exp", "needle_sweep_exp"]),
+    (SoundType::Random, &["random", "noise", "random_noise"]),
+    (SoundType::White, &["white", "white_random", "white_uniform", "white_noise"]),
+    (SoundType::WhiteNormal, &["white_normal", "white_random_normal", "white_gaussian", 
+        "white_random_gaussian", "white_gauss", "gaussian_noise"]),
+    (SoundType::WhiteTri, &["white_tri", "white_triangle", "white_triangular", "triangular_noise"]),
+    (SoundType::WhiteBin, &["white_bin", "white_binary", "white_bernoulli", "binary_noise", 
+        "bernoulli_noise"]),
+    (SoundType::PinkKelletEcon, &["pink_kellet_econ", "pke", "pink", "pink_noise"]),
+    (SoundType::PinkKelletRef, &["pink_kellet_ref", "pk3", "pink_ref"]),
+    (SoundType::Brown, &["brown", "red", "brownian", "brown_ema", "brown_noise"]),
+    (SoundType::BluePke, &["blue_pke", "blue", "azure", "blue_pke_ema", "blue_noise"]),
+    (SoundType::BluePk3, &["blue_pk3", "blue_pk3_ema", "blue_ref", "blue_ref_ema"]),
+    (SoundType::Violet, &["violet", "purple", "violet_ema", "violet_noise", "purple_noise"]),
+    (SoundType::PseudoVelvet, &["pseudo_velvet", "pseudo_velvet_noise", 
+        "pseudo_velvet_consecutive"]),
+];
+
+impl SoundType {
+    // Look up a sound type by any of its names
+    pub fn from_name(name: &str) -> Option<SoundType> {
+        SOUND_TYPES.iter().find(|(_, names)| names.contains(&name)).map(|(kind, _)| *kind)
+    }
+
+    // The canonical name of this sound type
+    pub fn name(self) -> &'static str {
+        SOUND_TYPES.iter().find(|(kind, _)| *kind == self).map(|(_, names)| names[0]).unwrap_or("")
+    }
+
+    // Whether this sound type is drawn from the random number generator
+    pub fn is_noise(self) -> bool {
+        matches!(self, SoundType::Random | SoundType::White | SoundType::WhiteNormal 
+            | SoundType::WhiteTri | SoundType::WhiteBin | SoundType::PinkKelletEcon 
+            | SoundType::PinkKelletRef | SoundType::Brown | SoundType::BluePke | SoundType::BluePk3 
+            | SoundType::Violet | SoundType::PseudoVelvet)
+    }
+
+    // Whether this sound type repeats the same cycle at its base frequency, from its very start
+    pub fn is_periodic(self) -> bool {
+        matches!(self, SoundType::Sine | SoundType::Cosine | SoundType::ClipSine | SoundType::QuantSine
+            | SoundType::Saw | SoundType::Square | SoundType::Triangle | SoundType::Pulse | SoundType::Sharktooth
+            | SoundType::SawBl | SoundType::SquareBl | SoundType::TriangleBl | SoundType::PulseBl
+            | SoundType::SharktoothBl | SoundType::DiracComb | SoundType::DiracCombNaive)
+    }
+
+    // Where in each cycle a periodic sound rises through zero, or steps up through it, as a phase
+    // from 0 to 1. Every one of them starts its first cycle there, except the cosine.
+    pub fn zero_crossing(self) -> f64 {
+        match self {
+            SoundType::Cosine => 0.75,
+            _ => 0.0,
+        }
+    }
+}
+
+// The user-facing parameters a sound is built from
+#[derive(Clone, Copy, Debug)]
+pub struct Params {
+    pub freq: f64,
+    pub freq_min: f64,
+    pub freq_max: f64,
+    pub offset: f64,
+    pub p1: f64,
+    pub p2: f64,
+    pub seed: u64,
+}
+
+// What a sweep changes over a render, and from what to what. Sweeps land exactly on their end
+// values, so where one passes any value in between is known to the frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sweep {
+    // A frequency in Hz, changing linearly, or exponentially for an even rate in octaves
+    Frequency { from: f64, to: f64, exponential: bool },
+    // A pulse width, from 0 to 1, changing linearly
+    Width { from: f64, to: f64 },
+}
+
+impl Sweep {
+    // How far through the render the sweep passes a value, from 0 at its first sample to 1 at its
+    // last, if it passes it at all
+    pub fn position(self, value: f64) -> Option<f64> {
+        let (from, to, value) = match self {
+            Sweep::Frequency { from, to, exponential: true } => (from.ln(), to.ln(), value.ln()),
+            Sweep::Frequency { from, to, .. } | Sweep::Width { from, to } => (from, to, value),
+        };
+        let position = (value - from) / (to - from);
+        (position.is_finite() && (0.0..=1.0).contains(&position)).then_some(position)
+    }
+
+    // A label for the point a sweep passes a value, like "1 kHz", "31.25 Hz", or "25% width"
+    pub fn label(self, value: f64) -> String {
+        let round = |value: f64| (value * 1e6).round() / 1e6;
+        match self {
+            Sweep::Frequency { .. } if value >= 1000.0 => format!("{} kHz", round(value / 1000.0)),
+            Sweep::Frequency { .. } => format!("{} Hz", round(value)),
+            Sweep::Width { .. } => format!("{}% width", round(value * 100.0)),
+        }
+    }
+}
+
+// How the generators on each channel of a multichannel render relate to one another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelMode {
+    Duplicate,
+    Independent,
+    FirstOnly,
+}
+
+// All channel mode names and aliases. The first name of each entry is its canonical name.
+pub const CHANNEL_MODES: &[(ChannelMode, &[&str])] = &[
+    (ChannelMode::Duplicate, &["duplicate", "dup", "same"]),
+    (ChannelMode::Independent, &["independent", "ind", "decorrelated"]),
+    (ChannelMode::FirstOnly, &["first-only", "first_only", "first"]),
+];
+
+impl ChannelMode {
+    // Look up a channel mode by any of its names
+    pub fn from_name(name: &str) -> Option<ChannelMode> {
+        CHANNEL_MODES.iter().find(|(_, names)| names.contains(&name)).map(|(mode, _)| *mode)
+    }
+
+    // The canonical name of this channel mode
+    pub fn name(self) -> &'static str {
+        CHANNEL_MODES.iter().find(|(mode, _)| *mode == self).map(|(_, names)| names[0]).unwrap_or("")
+    }
+
+    // The seed for a given channel's generator. Duplicated channels all share the one seed, so
+    // they render identically, while independent channels each get their own to decorrelate any
+    // noise. The first channel always keeps the given seed.
+    pub fn channel_seed(self, seed: u64, channel: u16) -> u64 {
+        match self {
+            ChannelMode::Independent => seed.wrapping_add(channel as u64),
+            ChannelMode::Duplicate | ChannelMode::FirstOnly => seed,
+        }
+    }
+}
+
+// Generic container to store information about our sound type, prepared once and then rendered
+// sample by sample. All randomness is drawn from the seeded rng here, so the same seed always
+// renders the same file.
 pub struct Sound {
+    pub kind: SoundType,
     pub sv: [f64; 8],
+    pub seed: u64,
+    pub rng: StdRng,
     pub freq: f64,
     pub freq_min: f64,
     pub freq_max: f64,
     pub offset: f64,
     pub p1: f64,
     pub p2: f64,
+    s: f64,
+    d: f64,
+    x: f64,
+    triangular: Triangular<f64>,
+    bernoulli: Bernoulli,
+}
+
+impl Sound {
+    pub fn new(kind: SoundType, params: &Params) -> Sound {
+        Sound {
+            kind,
+            sv: [0.0; 8],
+            seed: params.seed,
+            rng: StdRng::seed_from_u64(params.seed),
+            freq: params.freq,
+            freq_min: params.freq_min,
+            freq_max: params.freq_max,
+            offset: params.offset,
+            p1: params.p1,
+            p2: params.p2,
+            s: 48000.0,
+            d: 0.0,
+            x: 0.0,
+            triangular: Triangular::new(-1.0, 1.0, 0.0).unwrap(),
+            bernoulli: Bernoulli::new(0.5).unwrap(),
+        }
+    }
+
+    // A generator for dither, split off the state a sound with the given seed starts from. Its
+    // values come from a stream of their own, so dithering never takes any from the sound.
+    pub fn dither_rng(seed: u64) -> StdRng {
+        StdRng::from_rng(&mut StdRng::seed_from_u64(seed))
+    }
+}
+
+// Anything that can produce a signal one sample at a time. The built-in sound types all go through
+// Sound, but anything implementing this can be registered and rendered the same way.
+pub trait Generator {
+    // Set the sample rate and duration (in samples) that the generator will be rendered at
+    fn prepare(&mut self, sample_rate: f64, duration: u64);
+
+    // Produce the next sample
+    fn next_sample(&mut self) -> f64;
+
+    // Fill a whole buffer with consecutive samples
+    fn fill(&mut self, buffer: &mut [f64]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+
+    // Return to the very first sample, as if freshly prepared. A normalized render is measured,
+    // reset, then rendered again to write it, so every sample after a reset has to come out exactly
+    // as it did the first time, random or not, or the gain applied won't fit what is written.
+    fn reset(&mut self);
+
+    // Name shown in verbose output
+    fn name(&self) -> String {
+        String::from("custom")
+    }
+
+    // What the generator sweeps, for marking where it passes chosen values
+    fn sweep(&self) -> Option<Sweep> {
+        None
+    }
+}
+
+impl Generator for Sound {
+    // As with the windows, duration here is seen as sd - 1 so sweeps land exactly on their end
+    // values.
+    fn prepare(&mut self, sample_rate: f64, sd: u64) {
+        self.s = sample_rate;
+        self.d = sd as f64 - 1.0;
+        self.reset();
+    }
+
+    fn next_sample(&mut self) -> f64 {
+        let (s, d, x) = (self.s, self.d, self.x);
+        self.x += 1.0;
+        match self.kind {
+            SoundType::Silence => 0.0,
+            SoundType::Dc => 1.0,
+            SoundType::Sine => sine(s, self),
+            SoundType::Cosine => cosine(s, self),
+            SoundType::SweepLinSine => sweep_lin_sin(x, s, d, self),
+            SoundType::SweepExpSine => sweep_exp_sin(x, s, d, self),
+            SoundType::ClipSine => clip_sine(s, self),
+            SoundType::QuantSine => quant_sine(s, self),
+            SoundType::Saw => saw(s, self),
+            SoundType::Square => square(s, self),
+            SoundType::Triangle => triangle(s, self),
+            SoundType::Pulse => pulse(s, self),
+            SoundType::PulseSweepWidth => pulse_sweep_width(x, s, d, self),
+            SoundType::Sharktooth => sharktooth(s, self),
+            SoundType::SawBl => saw_bl(s, self),
+            SoundType::SquareBl => square_bl(s, self),
+            SoundType::TriangleBl => triangle_bl(s, self),
+            SoundType::PulseBl => pulse_bl(s, self),
+            SoundType::SharktoothBl => sharktooth_bl(s, self),
+            SoundType::UnitImpulse => unit_impulse(x, self),
+            SoundType::DiracComb => dirac_comb(s, self),
+            SoundType::DiracCombNaive => dirac_comb_naive(s, self),
+            SoundType::SweepLinComb => sweep_lin_comb(x, s, d, self),
+            SoundType::SweepExpComb => sweep_exp_comb(x, s, d, self),
+            SoundType::Random => random_uniform(self),
+            SoundType::White => white_random_uniform(self),
+            SoundType::WhiteNormal => white_random_normal(self),
+            SoundType::WhiteTri => white_random_tri(self),
+            SoundType::WhiteBin => white_random_bin(self),
+            SoundType::PinkKelletEcon => pink_kellet_econ(self),
+            SoundType::PinkKelletRef => pink_kellet_ref(self),
+            SoundType::Brown => brown_ema(s, self),
+            SoundType::BluePke => blue_pke_ema(s, self),
+            SoundType::BluePk3 => blue_pk3_ema(s, self),
+            SoundType::Violet => violet_ema(s, self),
+            SoundType::PseudoVelvet => pseudo_velvet_consecutive(self),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.sv = [0.0; 8];
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.x = 0.0;
+    }
+
+    fn name(&self) -> String {
+        self.kind.name().to_string()
+    }
+
+    fn sweep(&self) -> Option<Sweep> {
+        let (from, to) = (self.freq_min, self.freq_max);
+        match self.kind {
+            SoundType::SweepLinSine | SoundType::SweepLinComb => Some(Sweep::Frequency { from, to, exponential: false }),
+            SoundType::SweepExpSine | SoundType::SweepExpComb => Some(Sweep::Frequency { from, to, exponential: true }),
+            SoundType::PulseSweepWidth => Some(Sweep::Width { from: self.p1, to: self.p2 }),
+            _ => None,
+        }
+    }
+}
+
+// Correlated noise: a blend of one noise source shared by every channel and one of the channel's
+// own, both of the same type. As the two are uncorrelated and equal in level, mixing them with
+// gains of sqrt(c) and sqrt(1 - c) keeps the colour and level of the noise, while the correlation
+// between any two channels comes out as c. Only meaningful for noise, deterministic signals would
+// just be summed with themselves.
+pub struct Correlated {
+    common: Box<dyn Generator>,
+    own: Box<dyn Generator>,
+    common_gain: f64,
+    own_gain: f64,
+}
+
+impl Correlated {
+    pub fn new(common: Box<dyn Generator>, own: Box<dyn Generator>, correlation: f64) -> Correlated {
+        let correlation = correlation.clamp(0.0, 1.0);
+        Correlated { common, own, common_gain: correlation.sqrt(), own_gain: (1.0 - correlation).sqrt() }
+    }
+
+    // The seed for the shared source, kept apart from every channel seed derived from the same one
+    pub fn common_seed(seed: u64) -> u64 {
+        !seed
+    }
+}
+
+impl Generator for Correlated {
+    fn prepare(&mut self, sample_rate: f64, duration: u64) {
+        self.common.prepare(sample_rate, duration);
+        self.own.prepare(sample_rate, duration);
+    }
+
+    fn next_sample(&mut self) -> f64 {
+        (self.common_gain * self.common.next_sample()) + (self.own_gain * self.own.next_sample())
+    }
+
+    fn reset(&mut self) {
+        self.common.reset();
+        self.own.reset();
+    }
+
+    fn name(&self) -> String {
+        self.own.name()
+    }
+}
+
+// Everything needed to render one channel: its generator, gain envelope, and gain scalar
+pub struct Channel {
+    pub sound: Box<dyn Generator>,
+    pub window: Box<dyn Envelope>,
+    pub gain: f64,
+}
+
+// Where and how a render is written: the file, its format, and everything that happens to the
+// samples on their way out
+pub struct Output {
+    pub path: String,
+    // Stream to stdout instead of writing the file
+    pub stdout: bool,
+    pub format: Format,
+    pub spec: wav::Spec,
+    pub quantizer: Quantizer,
+    pub normalize: Option<Normalize>,
+    pub true_peak_limit: Option<f64>,
+    pub clip: bool,
+    pub strict: bool,
+    pub verbose: bool,
+    // What the file holds about the render besides its samples
+    pub metadata: Metadata,
 }
 
-pub fn create_sound(mut path: String, spec: hound::WavSpec, mut sound: Sound, sound_type: &str, duration: f64, window_type: &str,
-     window_k: f64, scalar: f64, verbose: bool) {
-    // Before creating the wav we need to ensure the given sound and window types are valid.
-    // This uses short dummy values to make sure we don't panic once we start writing the file.
-    match_sound(sound_type, 44100.0,2, 1.0, &mut sound);
-    window::match_window(window_type, window_k, 1, 1.0);
+// Clipping on one channel: how many samples were clipped, the longest run of them in a row, and
+// the furthest past full scale any of them went
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Clipping {
+    pub count: u64,
+    pub longest_run: u64,
+    pub max: f64,
+    run: u64,
+}
 
-    // Create a u32-cast version of our duration.
-    let duration_samples: u32 = duration as u32;
+impl Clipping {
+    // Count a sample if it is past full scale, giving back whether it was
+    fn count(&mut self, sample: f64) -> bool {
+        if sample.abs() > 1.0 {
+            self.count += 1;
+            self.run += 1;
+            self.longest_run = self.longest_run.max(self.run);
+            self.max = self.max.max(sample.abs());
+            true
+        } else {
+            self.run = 0;
+            false
+        }
+    }
+
+    // Clip a sample to full scale, counting it if it was past full scale
+    fn clip(&mut self, sample: &mut f64) {
+        if self.count(*sample) {
+            *sample = sample.signum();
+        }
+    }
+
+    // How far past full scale the worst clipped sample went, in dB
+    pub fn max_over_db(&self) -> f64 {
+        20.0 * self.max.log10()
+    }
+}
+
+// Render one frame of every channel, one sample each, before any clipping or quantizing
+fn render_frame(channels: &mut [Channel], t: u64, frame: &mut [f64]) {
+    for (sample, channel) in frame.iter_mut().zip(channels.iter_mut()) {
+        let source: f64 = channel.sound.next_sample();
+        let amplitude: f64 = channel.window.gain(t as f64);
+        *sample = channel.gain * amplitude * source;
+    }
+}
 
-    // This will be a scalar to adjust the output level depending on the bit depth. This value is
-    // effectively our "1.0" signal value for the given bit depth when writing our output.
-    let b: f64 = 2.0f64.powf(spec.bits_per_sample as f64 - 1.0) - 1.0;
+// Render each channel into a file, or stream it to stdout. Durations are in frames, so each channel
+// gets the full duration no matter how many channels there are. An infinite duration streams until
+// whatever is reading stops, and a stream that's closed early simply ends the render. In strict
+// mode a render that clipped, or went past full scale as float, is an error, and no file is written.
+pub fn create_sound(output: Output, channels: &mut [Channel], duration: f64) -> Result<String, Error> {
+    let Output { mut path, stdout, format, spec, mut quantizer, normalize, true_peak_limit, clip, strict, verbose, metadata } = output;
+    let extension = format!(".{}", format.extension());
 
-    // Before we create the wav, let's make sure there's not an existing file with the same name.
+    // Anything we have to say goes to stderr when the samples themselves are going to stdout.
+    macro_rules! say {
+        ($($arg:tt)*) => {if stdout {eprintln!($($arg)*)} else {println!($($arg)*)}};
+    }
+
+    // Create a u64-cast version of our duration. An endless render has its sounds and windows
+    // prepared for the longest duration there is, so anything that changes over time barely moves.
+    let endless = duration.is_infinite();
+    let duration_samples: u64 = duration as u64;
+    for channel in channels.iter_mut() {
+        channel.sound.prepare(spec.sample_rate as f64, duration_samples);
+        channel.window.prepare(duration_samples);
+    }
+
+    // Integer output can't go past full scale, so it is always clipped. Float output can, and is
+    // only clipped when asked for.
+    let clip = clip || spec.sample_format == wav::SampleFormat::Int;
+
+    // To normalize, the whole render is measured first, then the generators are reset so that the
+    // second pass renders exactly the same signal again, scaled to the target. This keeps memory
+    // use flat no matter how long or wide the render is.
+    let mut frame: Vec<f64> = vec![0.0; channels.len()];
+    let mut meter = Meter::new(&spec, normalize.and_then(|normalize| normalize.band()));
+    let mut normal_gain: f64 = 1.0;
+    if let Some(normalize) = normalize {
+        for t in 0 .. duration_samples {
+            render_frame(channels, t, &mut frame);
+            meter.add_frame(&frame);
+        }
+        match normalize.gain(&meter) {
+            Some(gain) => normal_gain = gain,
+            None => say!("\nWARNING: The output is silent, or too short to measure\nIt can't be normalized.\n\n"),
+        }
+        for channel in channels.iter_mut() {
+            channel.sound.reset();
+        }
+    }
+
+    // Before we create the file, let's make sure there's not an existing file with the same name.
     // If there is, increment on the name until a suitable alternative is found. 
-    if Path::new((path.clone() + ".wav").as_str()).exists() == true {
+    if !stdout && Path::new((path.clone() + &extension).as_str()).exists() {
         let mut i = 1;
         let mut exists = true;
-        while exists == true {
-            if Path::new((path.clone() + " (" + &i.to_string() + ").wav").as_str()).exists() == true {
+        while exists {
+            if Path::new((path.clone() + " (" + &i.to_string() + ")" + &extension).as_str()).exists() {
                 i += 1;
             } else {
                 path = path.clone() + " (" + &i.to_string() + ")";
@@ -57,82 +544,121 @@ pub fn create_sound(mut path: String, spec: hound::WavSpec, mut sound: Sound, so
     }
 
     // Here we calculate the output from our window, generator, and gain values (multiply them),
-    // then clip the output if the magnitude > 1.0 to avoid any errors on writing.
-    let mut writer = hound::WavWriter::create({path.clone()}+".wav", spec).unwrap(); 
-    let mut sample: f64;
-    for t in 0 .. duration_samples {
-        let source: f64 = match_sound(sound_type, spec.sample_rate as f64, duration_samples, t as f64, &mut sound);
-        let amplitude: f64 = window::match_window(window_type, window_k, duration_samples, t as f64);
-        sample = scalar * amplitude * source * b;
-        if sample.abs() > b {sample = sample.signum() * b}
-        writer.write_sample(sample as i32).unwrap();
+    // then clip the output if the magnitude > 1.0 where needed. Integer output is quantized, with
+    // any dither, on its way out. Each frame writes one sample per channel, interleaved in channel
+    // order.
+    // A true-peak limiter delays the signal by its look-ahead, so the render is followed by
+    // silence for as long, to flush the end of it back out.
+    let file_path = if stdout {String::from("stdout")} else {path.clone() + &extension};
+    let mut writer = if stdout {
+        let frames = (!endless).then_some(duration_samples);
+        format::create_stream(format, Box::new(io::stdout().lock()), spec, frames, &metadata)
+    } else {
+        format::create_writer(format, &file_path, spec, &metadata)
+    }.map_err(|e| Error::io(&file_path, e))?;
+    let mut limiter = true_peak_limit.map(|ceiling| Limiter::new(ceiling, spec.sample_rate as f64, channels.len()));
+    let latency = limiter.as_ref().map(|limiter| limiter.latency()).unwrap_or(0);
+    let mut true_peak = TruePeak::new(channels.len());
+    let mut clipping = vec![Clipping::default(); channels.len()];
+    let mut written: u64 = 0;
+    let end = if endless {u64::MAX} else {duration_samples + latency as u64};
+    'render: for t in 0 .. end {
+        if endless || t < duration_samples {
+            render_frame(channels, t, &mut frame);
+            frame.iter_mut().for_each(|sample| *sample *= normal_gain);
+        } else {
+            frame.fill(0.0);
+        }
+        if let Some(limiter) = limiter.as_mut() {
+            match limiter.process(&frame) {
+                Some(limited) => frame.copy_from_slice(&limited),
+                None => continue,
+            }
+        }
+        // Float output left unclipped is still counted in strict mode, where going past full scale
+        // fails the render just as clipping would.
+        if clip {
+            for (sample, clipping) in frame.iter_mut().zip(clipping.iter_mut()) {
+                clipping.clip(sample);
+            }
+        } else if strict {
+            for (sample, clipping) in frame.iter().zip(clipping.iter_mut()) {
+                clipping.count(*sample);
+            }
+        }
+        true_peak.add_frame(&frame);
+        for (i, sample) in frame.iter().enumerate() {
+            match match spec.sample_format {
+                wav::SampleFormat::Int => writer.write_int(quantizer.quantize(*sample, i)),
+                wav::SampleFormat::Float => writer.write_float(*sample),
+            } {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break 'render,
+                result => result.map_err(|e| Error::io(&file_path, e))?,
+            }
+        }
+        written += 1;
+    }
+
+    // A strict render that clipped is dropped before it is finalized, so it never lands on disk.
+    let clipped = clipping.iter().map(|clipping| clipping.count).sum::<u64>();
+    if clipped > 0 && strict {
+        return Err(Error::Clipped(clipped));
+    }
+    match writer.finalize() {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        result => result.map_err(|e| Error::io(&file_path, e))?,
+    }
+    if clipped > 0 {
+        say!("\nWARNING: {} samples clipped\nSee the verbose output for each channel.\n\n", clipped);
     }
 
     // Output information about the newly created file for verbose output
-    if verbose == true {
-        println!("\n\u{1F388}\u{1F388}\u{1F388} !!!!! YAY !!!!! \u{1F388}\u{1F388}\u{1F388}\n");
-        println!("Sound Type: \t{}", sound_type);
-        println!("Channels: \t{}", spec.channels);
-        println!("Sample Rate: \t{}", spec.sample_rate);
-        println!("Bit Depth: \t{}", spec.bits_per_sample);
-        println!("Duration:\n    Seconds:    {}\n    Samples:    {}", duration, duration_samples);
-        println!("Gain Window: \t{}", window_type);
-        println!("Gain Scalar: \t{:.1}", scalar);
-        println!("\nFile Successfully created at:\nfile://{}.wav", path.clone());
-    }
-}
-
-fn match_sound(sound_type: &str, s: f64, sd: u32, x: f64, sound: &mut Sound) -> f64 {
-    let d: f64 = sd as f64 - 1.0;
-    match sound_type {
-        "silence" | "silent" | "zero" | "null" => 0.0,
-        "dc" | "dc_offset" | "offset" | "constant" | "const" => 1.0,
-        "sin" | "sine" | "sine_wave" | "sinusoid" => sine(s, sound),
-        "cos" | "cosine" | "cosine_wave" => cosine(s, sound),
-        "sweep_lin" | "sweep_lin_sin" | "sweep_linear" | "chirp_lin" 
-        | "chirp_linear" | "lss" => sweep_lin_sin(x, s, d, sound),
-        "sweep" | "sweep_log" | "sweep_sin" | "sweep_log_sin" | "chirp" | "chirp_log" | 
-        "chirp_exp" | "ess" | "sweep_exp" | "sweep_exp_sin" | "log_sweep" 
-        | "exp_sweep" => sweep_exp_sin(x, s, d, sound),
-        "clip_sin" | "clip_sine" | "clipped_sine" | "hardclip_sine" | "hard_clip_sine" | 
-        "hardclipped_sine" | "hard_clipped_sine" => clip_sine(s, sound),
-        "quantized_sine" | "quantized_sin" | "quant_sine"
-        | "quant_sin" => quant_sine(s, sound),
-        "saw" | "sawtooth" | "saw_wave" => saw(s, sound),
-        "sqr" | "square" | "square_wave" => square(s, sound),
-        "tri" | "triangle" | "triangle_wave" => triangle(s, sound),
-        "pw" | "pwm" | "pulse" | "pulse_wave" => pulse(s, sound),
-        "pw_sweep" | "pwm_sweep" | "pulse_width_sweep" => pulse_sweep_width(x, s, d, sound),
-        "shark" | "sharktooth" | "sharktooth_wave" => sharktooth(s, sound),
-        "unit_impulse" | "dirac" | "delta" | "kronecker"
-        | "dirac_delta" | "kronecker_delta" | "click" => unit_impulse(x, sound),
-        "dirac_comb" | "impulse_train" | "needle" | "comb" 
-        | "needle_pulse" | "sha" => dirac_comb(s, sound),
-        /*
-        "sweep_lin_comb" | "dirac_comb_lin" | "linear_comb_sweep"
-        | "needle_lin" | "needle_sweep_lin" | "comb_lin" => sweep_lin_comb(x, d, s, sound),
-        "comb_sweep" | "sweep_comb" | "sweep_exp_comb" | "dirac_comb_exp" | "exp_comb_sweep"
-        | "needle_exp" | "needle_sweep_exp" | "comb_exp" => sweep_exp_comb(x, d, s, sound),
-        */
-        "random" | "noise" | "random_noise" => random_uniform(),
-        "white" | "white_random" | "white_uniform" | "white_noise" => white_random_uniform(),
-        "white_normal" | "white_random_normal" | "white_gaussian" | "white_random_gaussian" 
-        | "white_gauss" | "gaussian_noise" => white_random_normal(sound),
-        "white_tri" | "white_triangle" | "white_triangular" 
-        | "triangular_noise" => white_random_tri(),
-        "white_binary" | "white_bin" | "white_bernoulli" 
-        | "binary_noise" | "bernoulli_noise" => white_random_bin(),
-        "pink" | "pink_kellet_econ" | "pke" | "pink_noise" => pink_kellet_econ(sound),
-        "pink_ref" | "pink_kellet_ref" | "pk3" => pink_kellet_ref(sound),
-        "brown" | "red" | "brownian" | "brown_ema" | "brown_noise" => brown_ema(s, sound),
-        "blue" | "azure" | "blue_pke" | "blue_pke_ema" | "blue_noise" => blue_pke_ema(s, sound),
-        "blue_pk3" | "blue_pk3_ema" | "blue_ref" | "blue_ref_ema" => blue_pk3_ema(s, sound),
-        "violet" | "purple" | "violet_ema" | "violet_noise" 
-        | "purple_noise" => violet_ema(s, sound),
-        "pseudo_velvet" | "pseudo_velvet_noise" 
-        | "pseudo_velvet_consecutive" => pseudo_velvet_consecutive(sound),
-        _ => panic!("\n\n\tError:\tUnrecognized sound type.\n\n")
+    if verbose {
+        say!("\n\u{1F388}\u{1F388}\u{1F388} !!!!! YAY !!!!! \u{1F388}\u{1F388}\u{1F388}\n");
+        say!("Format: \t{}", format.name());
+        say!("Channels: \t{}", spec.channels);
+        if let Some(layout) = spec.layout {say!("Layout: \t{}", layout.name())}
+        say!("Sample Rate: \t{}", spec.sample_rate);
+        say!("Bit Depth: \t{}", spec.bits_per_sample);
+        say!("Sample Format: \t{}", spec.sample_format.name());
+        if spec.sample_format == wav::SampleFormat::Int {
+            say!("Dither: \t{}", quantizer.dither.name());
+            say!("Noise Shaping: \t{}", quantizer.shape.name());
+        }
+        say!("Duration:\n    Seconds:    {}\n    Frames:     {}", 
+            written as f64 / spec.sample_rate as f64, written);
+        for (i, channel) in channels.iter().enumerate() {
+            say!("Channel {}:", i + 1);
+            say!("    Speaker:     {}", spec.speaker(i as u16));
+            say!("    Sound Type:  {}", channel.sound.name());
+            say!("    Gain Window: {}", channel.window.name());
+            say!("    Gain Scalar: {:.1}", channel.gain);
+            if clipping[i].count > 0 {
+                say!("    Clipped:     {} samples, longest run {}, max {:+.2} dB over", 
+                    clipping[i].count, clipping[i].longest_run, clipping[i].max_over_db());
+            }
+        }
+        if let Some(normalize) = normalize {
+            say!("Normalize: \t{}", normalize.name());
+            say!("    Measured:    {:.2} {}", normalize.measured(&meter), normalize.unit());
+            say!("    Applied:     {:+.2} dB", 20.0 * normal_gain.log10());
+            if let Normalize::Lufs(_) = normalize {
+                say!("    Short-term Max:  {:.2} LUFS", meter.short_term_max() + 20.0 * normal_gain.log10());
+                say!("    Loudness Range:  {:.2} LU", meter.loudness_range());
+            }
+        }
+        say!("True Peak: \t{:.2} dBTP", true_peak.max_db());
+        if let Some(limiter) = limiter {
+            say!("True Peak Limit: {:.1} dBTP", limiter.ceiling);
+            say!("    Reduction:   {:.2} dB", 20.0 * limiter.reduction.log10());
+        }
+        if stdout {
+            say!("\nSuccessfully streamed to stdout");
+        } else {
+            say!("\nFile Successfully created at:\nfile://{}", file_path);
+        }
     }
+    Ok(file_path)
 }
 
 
@@ -205,7 +731,7 @@ fn saw(s: f64, sound: &mut Sound) -> f64 {
 fn square(s: f64, sound: &mut Sound) -> f64 {
     let phase = sound.sv[0];
     sound.sv[0] = (sound.sv[0] + (sound.freq / s)) % 1.0;
-    (-1.0 as f64).powf((2.0 * phase).floor())
+    (-1.0f64).powf((2.0 * phase).floor())
 }
 
 // Triangle (Constant frequency)
@@ -242,6 +768,96 @@ fn sharktooth(s: f64, sound: &mut Sound) -> f64 {
 // - - - - - - - - - - - - - - - - - - - - - - -
 
 
+//////-------------------------------------------
+//////      Band-Limited Waveshapes
+//////-------------------------------------------
+// [3]
+// These share their naive counterparts' phase accumulators and shapes, then add polynomial
+// residuals around each discontinuity. Steps (saw, square, pulse) are smoothed with PolyBLEP,
+// corners (triangle) with PolyBLAMP, and sharktooth needs both. Frequencies above a quarter of
+// the sample rate will have residuals overlapping one another, so they are best avoided.
+
+// PolyBLEP residual for a unit step, where t is the phase elapsed since the discontinuity
+fn poly_blep(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let t = t / dt;
+        -0.5 * (1.0 - t).powi(2)
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        0.5 * (1.0 + t).powi(2)
+    } else {
+        0.0
+    }
+}
+
+// PolyBLAMP residual for a unit change in slope per sample, t as in poly_blep
+fn poly_blamp(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let t = t / dt;
+        (1.0 - t).powi(3) / 6.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        (1.0 + t).powi(3) / 6.0
+    } else {
+        0.0
+    }
+}
+
+// Phase elapsed since a discontinuity sitting at phase "at", wrapped to 0 - 1
+fn phase_since(phase: f64, at: f64) -> f64 {
+    (phase - at).rem_euclid(1.0)
+}
+
+// Sawtooth Band-Limited (Constant frequency) -- falls by 2 at half phase
+fn saw_bl(s: f64, sound: &mut Sound) -> f64 {
+    let phase = sound.sv[0];
+    let dt = sound.freq / s;
+    let naive = saw(s, sound);
+    naive - (2.0 * poly_blep(phase_since(phase, 0.5), dt))
+}
+
+// Square Band-Limited (Constant frequency) -- rises by 2 at zero phase, falls by 2 at half phase
+fn square_bl(s: f64, sound: &mut Sound) -> f64 {
+    let phase = sound.sv[0];
+    let dt = sound.freq / s;
+    let naive = square(s, sound);
+    naive + (2.0 * (poly_blep(phase, dt) - poly_blep(phase_since(phase, 0.5), dt)))
+}
+
+// Triangle Band-Limited (Constant frequency) -- slope turns from +4 to -4 and back per cycle
+fn triangle_bl(s: f64, sound: &mut Sound) -> f64 {
+    let phase = sound.sv[0];
+    let dt = sound.freq / s;
+    let naive = triangle(s, sound);
+    let corner = 8.0 * dt;
+    naive + (corner * (poly_blamp(phase_since(phase, 0.75), dt) - poly_blamp(phase_since(phase, 0.25), dt)))
+}
+
+// Pulse Band-Limited (Constant frequency, width) -- rises at zero phase, falls at the width
+fn pulse_bl(s: f64, sound: &mut Sound) -> f64 {
+    let phase = sound.sv[0];
+    let dt = sound.freq / s;
+    let naive = pulse(s, sound);
+    if sound.p1 <= 0.0 || sound.p1 >= 1.0 {
+        return naive;
+    }
+    naive + (2.0 * (poly_blep(phase, dt) - poly_blep(phase_since(phase, sound.p1), dt)))
+}
+
+// Sharktooth Band-Limited (Constant frequency) -- steps of 0.5 at the quarter phases, where the
+// slope also turns from +2 to -4 and back again
+fn sharktooth_bl(s: f64, sound: &mut Sound) -> f64 {
+    let phase = sound.sv[0];
+    let dt = sound.freq / s;
+    let naive = sharktooth(s, sound);
+    let (q1, q3) = (phase_since(phase, 0.25), phase_since(phase, 0.75));
+    let corner = 6.0 * dt;
+    naive + (0.5 * (poly_blep(q1, dt) + poly_blep(q3, dt)))
+        + (corner * (poly_blamp(q3, dt) - poly_blamp(q1, dt)))
+}
+// - - - - - - - - - - - - - - - - - - - - - - -
+
+
 //////-------------------------------------------
 //////      Impulses
 //////-------------------------------------------
@@ -251,28 +867,50 @@ fn unit_impulse(x: f64, sound: &mut Sound) -> f64 {
     if x == sound.offset { 1.0 } else { 0.0 }
 }
 
+// Band-Limited Impulse Train "BLIT" [4]
+// A sum of equal-amplitude harmonics up to Nyquist, normalized so each pulse peaks at 1.0. The
+// pulses fall exactly on the phase wraps rather than the nearest sample, so no position jitter.
+// The harmonic count (held in sv[1]) can only change at the start of a period, otherwise the
+// pulse shape would be torn mid-cycle while sweeping.
+fn blit(phase: f64, dt: f64, sound: &mut Sound) -> f64 {
+    if sound.sv[1] == 0.0 || phase < dt {
+        sound.sv[1] = (2.0 * (0.5 / dt).floor()) + 1.0;
+    }
+    let m = sound.sv[1];
+    let denominator = m * (PI * phase).sin();
+    if denominator.abs() < f64::EPSILON { 1.0 } else { (PI * m * phase).sin() / denominator }
+}
+
 // Dirac Comb (Constant frequency)
 fn dirac_comb(s: f64, sound: &mut Sound) -> f64 {
+    let phase = sound.sv[0];
+    let dt = sound.freq / s;
+    sound.sv[0] = (sound.sv[0] + dt) % 1.0;
+    blit(phase, dt, sound)
+}
+
+// Dirac Comb Naive (Constant frequency, single-sample pulses -- aliases)
+fn dirac_comb_naive(s: f64, sound: &mut Sound) -> f64 {
     let phase = sound.sv[0];
     sound.sv[0] = (sound.sv[0] + (sound.freq / s)) % 1.0;
     ((phase - phase.floor()) < (sound.freq / s)) as i64 as f64
 }
 
-/*  Not Ready for primetime -- Need to implement anti-aliasing methods
-    // Dirac Comb Sweep Linear (Varying frequency)
-    fn sweep_lin_comb(x: f64, d: f64, s: f64, sound: &mut Sound) -> f64 {
-        let phase = sound.sv[0];
-        sound.sv[0] = (sound.sv[0] + ((sound.freq_min + ((sound.freq_max - sound.freq_min) * x / d)) / s)) % 1.0;
-        ((phase - phase.floor()) < (sound.freq / s)) as i64 as f64
-    }
+// Dirac Comb Sweep Linear (Varying frequency)
+fn sweep_lin_comb(x: f64, s: f64, d: f64, sound: &mut Sound) -> f64 {
+    let phase = sound.sv[0];
+    let dt = (sound.freq_min + ((sound.freq_max - sound.freq_min) * x / d)) / s;
+    sound.sv[0] = (sound.sv[0] + dt) % 1.0;
+    blit(phase, dt, sound)
+}
 
-    // Dirac Comb Sweep Exponential / Log (Varying frequency)
-    fn sweep_exp_comb(x: f64, d: f64, s: f64, sound: &mut Sound) -> f64 {
-        let phase = sound.sv[0];
-        sound.sv[0] = (sound.sv[0] + (10.0f64.powf(sound.freq_min.log10() + ((sound.freq_max.log10() - sound.freq_min.log10()) * x / d)) / s)) % 1.0;
-        ((phase - phase.floor()) < (sound.freq / s)) as i64 as f64
-    }
-*/
+// Dirac Comb Sweep Exponential / Log (Varying frequency)
+fn sweep_exp_comb(x: f64, s: f64, d: f64, sound: &mut Sound) -> f64 {
+    let phase = sound.sv[0];
+    let dt = 10.0f64.powf(sound.freq_min.log10() + ((sound.freq_max.log10() - sound.freq_min.log10()) * x / d)) / s;
+    sound.sv[0] = (sound.sv[0] + dt) % 1.0;
+    blit(phase, dt, sound)
+}
 // - - - - - - - - - - - - - - - - - - - - - - -
 
 
@@ -280,30 +918,31 @@ fn dirac_comb(s: f64, sound: &mut Sound) -> f64 {
 //////      Noise
 //////-------------------------------------------
 // Random Noise //
-// Random Uniform (Rust rand crate default -- ChaCha 12 round, StandardUniform distribution)
-fn random_uniform() -> f64 {
-    rand::random_range(-1.0..=1.0)
+// Random Uniform (Rust rand crate StdRng -- ChaCha 12 round, seeded, StandardUniform distribution)
+fn random_uniform(sound: &mut Sound) -> f64 {
+    sound.rng.random_range(-1.0..=1.0)
 }
 
 // White Noise //
 // White Random Uniform (Rust rand crate Uniform distribution, scaled)
-fn white_random_uniform() -> f64 {
-    0.21646117788 * random_uniform()
+fn white_random_uniform(sound: &mut Sound) -> f64 {
+    0.21646117788 * random_uniform(sound)
 }
 
-// White Random Normal / Gaussian (Rust rand_distr crate Normal distribution, scaled)
+// White Random Normal / Gaussian (Rust rand_distr crate Normal distribution, p1 std dev, scaled)
 fn white_random_normal(sound: &mut Sound) -> f64 {
-    0.12499856588 * Normal::new(0.0, sound.p1).unwrap().sample(&mut rand::rng()) as f64
+    let n: f64 = StandardNormal.sample(&mut sound.rng);
+    0.12499856588 * (sound.p1 * n)
 }
 
 // White Random Triangular (Rust rand_distr crate Triangular distribution, scaled)
-fn white_random_tri() -> f64 {
-    0.30616465062 * Triangular::new(-1.0, 1.0, 0.0).unwrap().sample(&mut rand::rng()) as f64
+fn white_random_tri(sound: &mut Sound) -> f64 {
+    0.30616465062 * sound.triangular.sample(&mut sound.rng)
 }
 
 // White Random Bernoulli / Binary (Rust rand crate bernoulli distribution, scaled)
-fn white_random_bin() -> f64 {
-    0.25 * (Bernoulli::new(0.5).unwrap().sample(&mut rand::rng()) as i32 as f64 - 0.5)
+fn white_random_bin(sound: &mut Sound) -> f64 {
+    0.25 * (sound.bernoulli.sample(&mut sound.rng) as i32 as f64 - 0.5)
 }
 // - - - - - - - - - - - - - - - - - - - - - - -
 
@@ -311,7 +950,7 @@ fn white_random_bin() -> f64 {
 // Pink Noise //
 // Pink Kellet Econ "pke" (Rust rand crate default, filtered with Kellet econ method)
 fn pink_kellet_econ(sound: &mut Sound) -> f64 {
-    let white: f64 = random_uniform();
+    let white: f64 = random_uniform(sound);
     sound.sv[0] = 0.99765 * sound.sv[0] + white * 0.0990460;
     sound.sv[1] = 0.96300 * sound.sv[1] + white * 0.2965164;
     sound.sv[2] = 0.57000 * sound.sv[2] + white * 1.0526913;
@@ -320,7 +959,7 @@ fn pink_kellet_econ(sound: &mut Sound) -> f64 {
 
 // Pink Kellet Refined "pk3" (Rust rand crate default, filtered with Kellet refined method)
 fn pink_kellet_ref(sound: &mut Sound) -> f64 {
-    let white: f64 = random_uniform();
+    let white: f64 = random_uniform(sound);
     sound.sv[0] = 0.99886 * sound.sv[0] + white * 0.0555179;
     sound.sv[1] = 0.99332 * sound.sv[1] + white * 0.0750759;
     sound.sv[2] = 0.96900 * sound.sv[2] + white * 0.1538520;
@@ -339,7 +978,7 @@ fn pink_kellet_ref(sound: &mut Sound) -> f64 {
 // Brown Noise (Rust rand crate default, filtered 6dB/oct EMA low pass)
 fn brown_ema(s: f64, sound: &mut Sound) -> f64 {
     let a: f64 = 20.0 / (s * 0.5);
-    sound.sv[0] = a * random_uniform() + (1.0 - a) * sound.sv[0];
+    sound.sv[0] = a * random_uniform(sound) + (1.0 - a) * sound.sv[0];
     10.6143507417 * sound.sv[0]
 }
 // - - - - - - - - - - - - - - - - - - - - - - -
@@ -349,7 +988,7 @@ fn brown_ema(s: f64, sound: &mut Sound) -> f64 {
 // Blue PKE_EMA (Rust rand crate default, filtered w/ Kellet econ, then 6dB/oct EMA high pass)
 fn blue_pke_ema(s: f64, sound: &mut Sound) -> f64 {
     let a: f64 = 20000.0 / (s * 0.5);
-    let white: f64 = random_uniform();
+    let white: f64 = random_uniform(sound);
 
     // Kellet Econ
     sound.sv[0] = 0.99765 * sound.sv[0] + white * 0.0990460;
@@ -365,7 +1004,7 @@ fn blue_pke_ema(s: f64, sound: &mut Sound) -> f64 {
 // Blue PK3_EMA (Rust rand crate default, filtered with Kellet ref, then 6dB/oct EMA high pass)
 fn blue_pk3_ema(s: f64, sound: &mut Sound) -> f64 {
     let a: f64 = 20000.0 / (s * 0.5);
-    let white: f64 = random_uniform();
+    let white: f64 = random_uniform(sound);
 
     // Kellet Ref
     sound.sv[0] = 0.99886 * sound.sv[0] + white * 0.0555179;
@@ -389,7 +1028,7 @@ fn blue_pk3_ema(s: f64, sound: &mut Sound) -> f64 {
 // Violet Noise (Rust rand crate default, filtered 6dB/oct EMA high pass)
 fn violet_ema(s: f64, sound: &mut Sound) -> f64 {
     let a: f64 = 20000.0 / (s * 0.5);
-    let white: f64 = random_uniform();
+    let white: f64 = random_uniform(sound);
     sound.sv[0] = a * white + (1.0 - a) * sound.sv[0];
     0.99206475709 * (white - sound.sv[0])
 }
@@ -399,7 +1038,7 @@ fn violet_ema(s: f64, sound: &mut Sound) -> f64 {
 // Velvet Noise //
 // Velvet Round (Rust rand crate default, threshold to 0 between abs() our sparsity parameter)
 fn pseudo_velvet_consecutive(sound: &mut Sound) -> f64 {
-    let rand = random_uniform();
+    let rand = random_uniform(sound);
     if rand.abs() < sound.p1 {
         0.0
     }
@@ -408,3 +1047,125 @@ fn pseudo_velvet_consecutive(sound: &mut Sound) -> f64 {
     }
 }
 // - - - - - - - - - - - - - - - - - - - - - - -
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every sound type drawn from the rng
+    const NOISE: &[SoundType] = &[SoundType::Random, SoundType::White, SoundType::WhiteNormal,
+        SoundType::WhiteTri, SoundType::WhiteBin, SoundType::PinkKelletEcon, SoundType::PinkKelletRef,
+        SoundType::Brown, SoundType::BluePke, SoundType::BluePk3, SoundType::Violet, SoundType::PseudoVelvet];
+
+    fn params(seed: u64) -> Params {
+        Params { freq: 1000.0, freq_min: 20.0, freq_max: 20000.0, offset: 0.0, p1: 0.5, p2: 0.5, seed }
+    }
+
+    fn render(generator: &mut dyn Generator, frames: usize) -> Vec<f64> {
+        let mut buffer = vec![0.0; frames];
+        generator.fill(&mut buffer);
+        buffer
+    }
+
+    fn sound(kind: SoundType, seed: u64) -> Sound {
+        let mut sound = Sound::new(kind, &params(seed));
+        sound.prepare(48000.0, 4800);
+        sound
+    }
+
+    #[test]
+    fn noise_is_seeded() {
+        assert!(NOISE.iter().all(|kind| kind.is_noise()));
+        for &kind in NOISE {
+            let first = render(&mut sound(kind, 5), 4800);
+            assert_eq!(first, render(&mut sound(kind, 5), 4800), "{} with the same seed", kind.name());
+            assert_ne!(first, render(&mut sound(kind, 6), 4800), "{} with another seed", kind.name());
+        }
+    }
+
+    // Normalizing renders twice, and counts on reset to bring back the very same signal
+    #[test]
+    fn reset_repeats_the_render() {
+        for &kind in NOISE.iter().chain(&[SoundType::Sine, SoundType::SweepExpSine, SoundType::SweepExpComb]) {
+            let mut sound = sound(kind, 5);
+            let first = render(&mut sound, 4800);
+            sound.reset();
+            assert_eq!(first, render(&mut sound, 4800), "{} after a reset", kind.name());
+        }
+    }
+    #[test]
+    fn clipping_counts() {
+        let mut clipping = Clipping::default();
+        let mut samples = [0.5, 1.0, 1.5, -2.0, 1.1, 0.0, -1.25, 0.9, 1.0001];
+        for sample in samples.iter_mut() {
+            clipping.clip(sample);
+        }
+        assert_eq!(samples, [0.5, 1.0, 1.0, -1.0, 1.0, 0.0, -1.0, 0.9, 1.0]);
+        assert_eq!((clipping.count, clipping.longest_run, clipping.max), (5, 3, 2.0));
+        assert!((clipping.max_over_db() - 6.0206).abs() < 1e-4);
+    }
+
+    // Counting alone leaves float samples past full scale as they are
+    #[test]
+    fn overshoot_counts() {
+        let mut clipping = Clipping::default();
+        let overs: Vec<bool> = [1.5, 1.4, 0.2, -1.0, -1.2].iter().map(|&sample| clipping.count(sample)).collect();
+        assert_eq!(overs, [true, true, false, false, true]);
+        assert_eq!((clipping.count, clipping.longest_run, clipping.max), (3, 2, 1.5));
+    }
+    // Pearson's correlation coefficient of two signals
+    fn correlation(a: &[f64], b: &[f64]) -> f64 {
+        let mean = |x: &[f64]| x.iter().sum::<f64>() / x.len() as f64;
+        let (mean_a, mean_b) = (mean(a), mean(b));
+        let covariance: f64 = a.iter().zip(b).map(|(a, b)| (a - mean_a) * (b - mean_b)).sum();
+        let spread = |x: &[f64], mean: f64| x.iter().map(|x| (x - mean).powi(2)).sum::<f64>().sqrt();
+        covariance / (spread(a, mean_a) * spread(b, mean_b))
+    }
+
+    #[test]
+    fn channel_seeds() {
+        for mode in [ChannelMode::Duplicate, ChannelMode::FirstOnly] {
+            assert!((0 .. 8).all(|channel| mode.channel_seed(5, channel) == 5), "{}", mode.name());
+        }
+        let seeds: Vec<u64> = (0 .. 8).map(|channel| ChannelMode::Independent.channel_seed(u64::MAX, channel)).collect();
+        assert_eq!(seeds[0], u64::MAX);
+        assert!(seeds.iter().enumerate().all(|(i, seed)| !seeds[i + 1 ..].contains(seed)));
+
+        // Duplicated channels render identically, while independent noise is uncorrelated
+        let render_channel = |mode: ChannelMode, channel: u16| render(&mut sound(SoundType::White, mode.channel_seed(5, channel)), 48000);
+        assert_eq!(render_channel(ChannelMode::Duplicate, 0), render_channel(ChannelMode::Duplicate, 1));
+        let r = correlation(&render_channel(ChannelMode::Independent, 0), &render_channel(ChannelMode::Independent, 1));
+        assert!(r.abs() < 0.02, "independent channels correlate by {}", r);
+    }
+    // Two channels of correlated noise, built as the command line builds them
+    fn correlated(kind: SoundType, correlation: f64, channel: u16) -> Correlated {
+        let common = sound(kind, Correlated::common_seed(5));
+        let own = sound(kind, ChannelMode::Independent.channel_seed(5, channel));
+        let mut correlated = Correlated::new(Box::new(common), Box::new(own), correlation);
+        correlated.prepare(48000.0, 48000);
+        correlated
+    }
+
+    // The correlation between channels comes out as asked for, with the level of the noise kept
+    #[test]
+    fn correlated_noise() {
+        let rms = |x: &[f64]| (x.iter().map(|x| x * x).sum::<f64>() / x.len() as f64).sqrt();
+        // A second of pink noise is dominated by its lowest frequencies, so it varies the most
+        for (kind, tolerance, level_tolerance) in [(SoundType::White, 0.02, 0.02), (SoundType::PinkKelletEcon, 0.08, 0.1),
+                (SoundType::Violet, 0.02, 0.02)] {
+            let level = rms(&render(&mut sound(kind, 5), 48000));
+            for c in [0.0, 0.5, 1.0] {
+                let (left, right) = (render(&mut correlated(kind, c, 0), 48000), render(&mut correlated(kind, c, 1), 48000));
+                let r = correlation(&left, &right);
+                assert!((r - c).abs() < tolerance, "{} at {} correlates by {}", kind.name(), c, r);
+                assert!((rms(&right) / level - 1.0).abs() < level_tolerance, "{} at {} has a level of {}", kind.name(), c, rms(&right));
+            }
+        }
+
+        let mut channel = correlated(SoundType::White, 0.5, 1);
+        let first = render(&mut channel, 4800);
+        channel.reset();
+        assert_eq!(first, render(&mut channel, 4800));
+    }
+}
@@ -0,0 +1,235 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//      R E A D I N G   F I L E S   B A C K    //
+//                                             //
+/////////////////////////////////////////////////
+
+// Reads back what a file says about itself: its format, and the tags homados wrote describing the
+// render. WAV files keep them in the comment of their LIST INFO chunk, one "NAME=value" per line,
+// and FLAC files as Vorbis comments. Only the chunks that matter are read, so even a file of many
+// gigabytes is inspected at once. AIFF files hold no tags, so they aren't read.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use crate::error::Error;
+use crate::format::Format;
+use crate::wav::{Container, SampleFormat};
+
+// Where to find the samples of a file, to tell whether two files hold the same ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Samples {
+    // The byte range of a WAV file's data chunk
+    Range { offset: u64, len: u64 },
+    // The MD5 of a FLAC file's samples, from its STREAMINFO
+    Md5([u8; 16]),
+}
+
+// Everything a file says about itself
+#[derive(Clone, Debug)]
+pub struct Info {
+    pub format: Format,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    pub sample_format: SampleFormat,
+    pub frames: u64,
+    pub tags: Vec<(String, String)>,
+    pub samples: Samples,
+}
+
+impl Info {
+    // The value of a tag, by its name
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+}
+
+// Read the format and tags of a WAV, RF64, Wave64, or FLAC file
+pub fn read(path: &str) -> Result<Info, Error> {
+    let unreadable = |e: io::Error| Error::unreadable(path, &e.to_string());
+    let mut file = BufReader::new(File::open(path).map_err(unreadable)?);
+    let file_len = file.get_ref().metadata().map_err(unreadable)?.len();
+    let magic: [u8; 4] = bytes(&mut file).map_err(unreadable)?;
+    file.rewind().map_err(unreadable)?;
+    match &magic {
+        b"RIFF" => read_wav(&mut file, file_len, Container::Riff),
+        b"RF64" => read_wav(&mut file, file_len, Container::Rf64),
+        b"riff" => read_wav(&mut file, file_len, Container::Wave64),
+        b"fLaC" => read_flac(&mut file),
+        b"FORM" => Err(invalid("AIFF files don't hold the tags of a render")),
+        _ => Err(invalid("it isn't a WAV or FLAC file")),
+    }.map_err(unreadable)
+}
+
+// Whether two files hold the same samples: by their MD5s for FLAC, and their data chunks byte for
+// byte otherwise. Files in different formats can't be compared, and give None.
+pub fn same_samples(path: &str, info: &Info, other_path: &str, other: &Info) -> Result<Option<bool>, Error> {
+    if (info.channels, info.sample_rate, info.bits_per_sample, info.sample_format, info.frames)
+        != (other.channels, other.sample_rate, other.bits_per_sample, other.sample_format, other.frames) {
+        return Ok(Some(false));
+    }
+    match (info.samples, other.samples) {
+        (Samples::Md5(md5), Samples::Md5(other_md5)) => Ok(Some(md5 == other_md5)),
+        (Samples::Range { offset, len }, Samples::Range { offset: other_offset, len: other_len }) => {
+            if len != other_len {return Ok(Some(false))}
+            let open = |path: &str, offset: u64| -> Result<_, Error> {
+                let mut file = BufReader::new(File::open(path).map_err(|e| Error::unreadable(path, &e.to_string()))?);
+                file.seek(SeekFrom::Start(offset)).map_err(|e| Error::unreadable(path, &e.to_string()))?;
+                Ok(file.take(len))
+            };
+            let (mut a, mut b) = (open(path, offset)?, open(other_path, other_offset)?);
+            let (mut a_buffer, mut b_buffer) = (vec![0u8; 1 << 16], vec![0u8; 1 << 16]);
+            loop {
+                let read = a.read(&mut a_buffer).map_err(|e| Error::unreadable(path, &e.to_string()))?;
+                if read == 0 {return Ok(Some(true))}
+                b.read_exact(&mut b_buffer[..read]).map_err(|e| Error::unreadable(other_path, &e.to_string()))?;
+                if a_buffer[..read] != b_buffer[..read] {return Ok(Some(false))}
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+// RIFF, RF64, and Wave64 files all hold the same chunks, with different headers around them. A
+// Wave64 chunk is named by a GUID starting with its RIFF name, and its size counts its own 24 byte
+// header. A data chunk whose size is unknown, as in an endless stream, runs to the end of the file.
+fn read_wav(file: &mut (impl Read + Seek), file_len: u64, container: Container) -> io::Result<Info> {
+    let (mut offset, header_len) = if container == Container::Wave64 {(40, 24)} else {(12, 8)};
+    let mut fmt: Option<Vec<u8>> = None;
+    let mut tags: Vec<(String, String)> = Vec::new();
+    let mut data: Option<(u64, u64)> = None;
+    let mut ds64_data_len: Option<u64> = None;
+    while offset + header_len <= file_len {
+        file.seek(SeekFrom::Start(offset))?;
+        let (id, len) = if container == Container::Wave64 {
+            let guid: [u8; 16] = bytes(file)?;
+            let len = u64::from_le_bytes(bytes(file)?).saturating_sub(24);
+            ([guid[0], guid[1], guid[2], guid[3]], len)
+        } else {
+            (bytes(file)?, u32::from_le_bytes(bytes(file)?) as u64)
+        };
+        let body = offset + header_len;
+        let len = match &id {
+            b"data" if container == Container::Rf64 && len == u32::MAX as u64 => ds64_data_len.unwrap_or(len),
+            b"data" if container == Container::Riff && len == u32::MAX as u64 => file_len - body,
+            b"data" if container == Container::Wave64 && len == u64::MAX - 24 => file_len - body,
+            _ => len,
+        }.min(file_len - body);
+        match &id {
+            b"ds64" => {
+                let ds64 = body_bytes(file, len)?;
+                ds64_data_len = ds64.get(8..16).map(|size| u64::from_le_bytes(size.try_into().unwrap_or_default()));
+            }
+            b"fmt " => fmt = Some(body_bytes(file, len)?),
+            b"LIST" | b"list" => tags.extend(info_tags(&body_bytes(file, len)?)),
+            b"data" => data = Some((body, len)),
+            _ => {}
+        }
+        offset = body + len + container.padding(len);
+    }
+
+    let fmt = fmt.filter(|fmt| fmt.len() >= 16).ok_or_else(|| invalid("it has no fmt chunk"))?;
+    let (offset, len) = data.ok_or_else(|| invalid("it has no data chunk"))?;
+    let word = |at: usize| u16::from_le_bytes([fmt[at], fmt[at + 1]]);
+    let (format_tag, bits_per_sample) = match word(0) {
+        0xfffe if fmt.len() >= 26 => (word(24), word(18)),
+        format_tag => (format_tag, word(14)),
+    };
+    let block_align = word(12).max(1) as u64;
+    Ok(Info {
+        format: match container {Container::Riff => Format::Wav, Container::Rf64 => Format::Rf64, Container::Wave64 => Format::Wave64},
+        channels: word(2),
+        sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+        bits_per_sample,
+        sample_format: if format_tag == 3 {SampleFormat::Float} else {SampleFormat::Int},
+        frames: len / block_align,
+        tags,
+        samples: Samples::Range { offset, len },
+    })
+}
+
+// The tags in the comment of a LIST INFO chunk, one "NAME=value" per line
+fn info_tags(list: &[u8]) -> Vec<(String, String)> {
+    let mut tags: Vec<(String, String)> = Vec::new();
+    if !list.starts_with(b"INFO") {return tags}
+    let mut at = 4;
+    while at + 8 <= list.len() {
+        let len = u32::from_le_bytes([list[at + 4], list[at + 5], list[at + 6], list[at + 7]]) as usize;
+        let text = &list[(at + 8).min(list.len()) .. (at + 8 + len).min(list.len())];
+        if &list[at .. at + 4] == b"ICMT" {
+            let text = String::from_utf8_lossy(text);
+            tags.extend(text.trim_end_matches('\0').lines().filter_map(|line| line.split_once('='))
+                .map(|(name, value)| (name.to_string(), value.to_string())));
+        }
+        at += 8 + len + len % 2;
+    }
+    tags
+}
+
+// FLAC keeps the format in its STREAMINFO block, along with the MD5 of the samples, and the tags
+// in its Vorbis comment block. The frames after them aren't read at all.
+fn read_flac(file: &mut (impl Read + Seek)) -> io::Result<Info> {
+    file.seek(SeekFrom::Start(4))?;
+    let mut stream_info: Option<Vec<u8>> = None;
+    let mut tags: Vec<(String, String)> = Vec::new();
+    loop {
+        let header: [u8; 4] = bytes(file)?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        match header[0] & 0x7f {
+            0 => stream_info = Some(body_bytes(file, len)?),
+            4 => tags = vorbis_tags(&body_bytes(file, len)?),
+            _ => {file.seek(SeekFrom::Current(len as i64))?;}
+        }
+        if header[0] & 0x80 != 0 {break}
+    }
+
+    let stream_info = stream_info.filter(|block| block.len() >= 34).ok_or_else(|| invalid("it has no STREAMINFO"))?;
+    let fields = u64::from_be_bytes(stream_info[10..18].try_into().unwrap_or_default());
+    Ok(Info {
+        format: Format::Flac,
+        channels: ((fields >> 41) & 0x7) as u16 + 1,
+        sample_rate: (fields >> 44) as u32,
+        bits_per_sample: ((fields >> 36) & 0x1f) as u16 + 1,
+        sample_format: SampleFormat::Int,
+        frames: fields & ((1 << 36) - 1),
+        tags,
+        samples: Samples::Md5(stream_info[18..34].try_into().unwrap_or_default()),
+    })
+}
+
+// The comments of a Vorbis comment block, after its vendor string
+fn vorbis_tags(block: &[u8]) -> Vec<(String, String)> {
+    let mut tags: Vec<(String, String)> = Vec::new();
+    let length = |at: usize| block.get(at .. at + 4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]) as usize);
+    let Some(vendor) = length(0) else {return tags};
+    let Some(count) = length(4 + vendor) else {return tags};
+    let mut at = 8 + vendor;
+    for _ in 0 .. count {
+        let Some(len) = length(at) else {break};
+        let Some(comment) = block.get(at + 4 .. at + 4 + len) else {break};
+        if let Some((name, value)) = String::from_utf8_lossy(comment).split_once('=') {
+            tags.push((name.to_string(), value.to_string()));
+        }
+        at += 4 + len;
+    }
+    tags
+}
+
+fn bytes<const N: usize>(file: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// The body of a chunk or block, which for the ones read here is never more than a few kilobytes
+fn body_bytes(file: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    if len > 1 << 24 {return Err(invalid("a chunk is too large"))}
+    let mut body = vec![0u8; len as usize];
+    file.read_exact(&mut body)?;
+    Ok(body)
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
@@ -0,0 +1,36 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//                h o m a d o s                //
+//          Signal should be simple.           //
+//                                             //
+/////////////////////////////////////////////////
+
+// homados as a library. Everything the command line tool renders is available here too, so other
+// tools can generate signals in memory without shelling out and reading the files back in.
+//
+//  - error:        the errors that can stop a render
+//  - generator:    the Generator trait, plus the built-in Sound and its sound types
+//  - window:       the Envelope trait, plus the built-in Window and its curve shapes
+//  - registry:     name and alias lookup for all of the above, extendable with your own
+//  - dither:       dither and noise shaping for quantizing to integer samples
+//  - normalize:    measuring a render and normalizing it to a target level
+//  - peak:         true-peak measurement, and the true-peak limiter
+//  - format:       the output formats, and the Writer trait they're all written through
+//  - wav:          the WAV file writer, with its speaker layouts and channel masks
+//  - flac:         the FLAC encoder
+//  - aiff:         the AIFF and AIFF-C file writer
+//  - inspect:      reading the format and tags of a written file back in
+
+pub mod aiff;
+pub mod dither;
+pub mod error;
+pub mod flac;
+pub mod format;
+pub mod generator;
+pub mod inspect;
+pub mod normalize;
+pub mod peak;
+pub mod registry;
+pub mod wav;
+pub mod window;
@@ -9,191 +9,908 @@
 // Welcome to homados :)
 
 use std::fs;
+use std::io::{self, IsTerminal};
 use std::path::Path;
-use clap::Parser;
-use hound;
+use std::time::{SystemTime, UNIX_EPOCH};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap::parser::ValueSource;
+use homados::dither::{DITHERS, Dither, NOISE_SHAPES, NoiseShape, Quantizer};
+use homados::error::Error;
+use homados::format::{FORMATS, Format, Loop, MARKER_SETS, Marker, MarkerSet, Metadata};
+use homados::generator;
+use homados::inspect;
+use homados::normalize::{Normalize, dbfs_to_amp};
+use homados::registry::Registry;
+use homados::wav;
 
-mod generator;
-mod window;
+// The current date and time in UTC, as "YYYY-MM-DDTHH:MM:SSZ"
+fn utc_now() -> String {
+    utc_date(SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0))
+}
+
+// A time in seconds since 1970 as a UTC date. The date is worked out from the days since 1970 with
+// Howard Hinnant's civil_from_days, which counts in 400 year eras starting in March.
+fn utc_date(now: u64) -> String {
+    let (days, seconds) = (now / 86400 + 719468, now % 86400);
+    let (era, day_of_era) = (days / 146097, days % 146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 {month + 3} else {month - 9};
+    let year = era * 400 + year_of_era + (month <= 2) as u64;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
 
-// Convert dBFS value to amplitude
-fn dbfs_to_amp(input: f64) -> f64 {
-    10.0f64.powf(input / 20.0)
+// Pick a channel's value from a per-channel list. Channels past the end of the list reuse its last
+// value, so a single value applies to every channel.
+fn per_channel<T: Clone>(values: &[T], channel: u16) -> T {
+    values[(channel as usize).min(values.len() - 1)].clone()
 }
 
 // We'll use clap to handle all our command line input logistics and set up some opinionated
 // default values to make creating sound as simple as one wants it to be.
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Output file path destination
     #[arg(default_value = "./homados Output", hide_default_value = true)]
     path: String,
 
-    /// Output file name
+    /// Output file name, where an extension (.wav, .w64, .flac, .aiff) also picks the format
     #[arg(default_value = "homados_output", hide_default_value = true)]
     name: String,
 
+    /// Output file format: wav, rf64, w64, flac, or aiff. A WAV too long for 4GB is written as
+    /// RF64. Raw PCM can be streamed as s16le, s24le, s32le, f32le, or f64le
+    #[arg(long = "Format", required = false, value_name = "String")]
+    format: Option<String>,
+
+    /// Stream to stdout instead of writing a file, as WAV or raw PCM. With a duration of "inf",
+    /// the stream goes on until whatever is reading it stops.
+    #[arg(long = "Stdout")]
+    stdout: bool,
+
+    /// Mark a loop over whole cycles of a periodic sound, for samplers (WAV, AIFF)
+    #[arg(long = "Loop")]
+    looped: bool,
+
+    /// Mark where a sweep passes each "octave" or "decade", or a comma separated list of
+    /// frequencies or pulse widths, and the middle of each fade with "window" (WAV)
+    #[arg(long = "Markers", required = false, value_name = "String", value_delimiter = ',')]
+    markers: Vec<String>,
+
     /// Sample Rate
-    #[arg(short = 's', long = "SampleRate", required = false, value_name = "Positive Int", 
+    #[arg(short = 's', long = "SampleRate", required = false, value_name = "Positive Int",
             default_value = "48000", hide_default_value = true)]
     rate: u32,
 
-    /// Bit Depth
-    #[arg(short = 'b', long = "BitDepth", required = false, value_name = "Positive Int", 
-            default_value = "24", hide_default_value = true)]
-    bitdepth: u16,
+    /// Bit Depth, where a trailing "f" (32f, 64f) selects float output
+    #[arg(short = 'b', long = "BitDepth", required = false, value_name = "Positive Int")]
+    bitdepth: Option<String>,
+
+    /// Sample format: int, or float
+    #[arg(long = "SampleFormat", required = false, value_name = "String")]
+    sample_format: Option<String>,
+
+    /// Clip float output to full scale, as integer output always is
+    #[arg(long = "Clip")]
+    clip: bool,
+
+    /// Normalize the output to a target level, as "peak=-1dBFS", "lufs=-23", or "rms=-20dBFS"
+    #[arg(long = "Normalize", required = false, value_name = "String", allow_hyphen_values = true)]
+    normalize: Option<String>,
+
+    /// Normalize the output to a target RMS level in dBFS, the same as "--Normalize rms=<dBFS>"
+    #[arg(long = "RMS", required = false, value_name = "Float", allow_hyphen_values = true)]
+    rms: Option<String>,
+
+    /// Only measure RMS between the minimum and maximum frequency
+    #[arg(long = "RMSBand")]
+    rms_band: bool,
+
+    /// Reference RMS to a full scale sine (AES17), rather than a full scale square
+    #[arg(long = "AES17")]
+    aes17: bool,
+
+    /// Limit true peaks to a ceiling in dBTP, as "-1dBTP"
+    #[arg(long = "TruePeakLimit", required = false, value_name = "Float", allow_hyphen_values = true)]
+    true_peak_limit: Option<String>,
+
+    /// Fail, leaving no file behind, if any sample clipped, or went past full scale as float
+    #[arg(long = "Strict")]
+    strict: bool,
+
+    /// Dither for integer output: none, rpdf, tpdf, or hp-tpdf
+    #[arg(long = "Dither", required = false, value_name = "String",
+            default_value = "none", hide_default_value = true)]
+    dither: String,
+
+    /// Noise shaping for integer output: none, simple, or f-weighted
+    #[arg(long = "NoiseShaping", required = false, value_name = "String",
+            default_value = "none", hide_default_value = true)]
+    noise_shaping: String,
 
     /// Channel Count
-    #[arg(short = 'c', long = "ChannelCount", required = false, value_name = "Positive Int", 
+    #[arg(short = 'c', long = "ChannelCount", required = false, value_name = "Positive Int",
             default_value = "1", hide_default_value = true)]
     channels: u16,
-    
-    /// Type of sound
-    #[arg(short = 't', long = "SoundType", required = false, value_name = "String", 
-            default_value = "white", hide_default_value = true)]
-    sound_type: String,
+
+    /// Speaker layout, which also sets the channel count: mono, stereo, LCR, quad, 5.0, 5.1, 7.1,
+    /// 7.1.4, or ambisonic
+    #[arg(long = "Layout", required = false, value_name = "String")]
+    layout: Option<String>,
+
+    /// How channels relate: duplicate, independent, or first-only
+    #[arg(long = "ChannelMode", required = false, value_name = "String")]
+    channel_mode: Option<String>,
+
+    /// Correlation between the noise on each channel, from 0.0 (independent) to 1.0 (identical)
+    #[arg(long = "Correlation", required = false, value_name = "Float")]
+    correlation: Option<f64>,
+
+    /// Type of sound, or a comma separated list with one per channel
+    #[arg(short = 't', long = "SoundType", required = false, value_name = "String",
+            default_value = "white", hide_default_value = true, value_delimiter = ',')]
+    sound_type: Vec<String>,
 
     /// Duration of sound in seconds
-    #[arg(short = 'd', long = "DurationSeconds", required = false, value_name = "Positive Float", 
+    #[arg(short = 'd', long = "DurationSeconds", required = false, value_name = "Positive Float",
             default_value = "10.0", hide_default_value = true)]
     duration_seconds: f64,
 
     /// Duration of sound in samples
-    #[arg(short = 'D', long = "DurationSamples", required = false, value_name = "Positive Float", 
+    #[arg(short = 'D', long = "DurationSamples", required = false, value_name = "Positive Float",
             default_value = "480000", hide_default_value = true)]
     duration_samples: f64,
 
-    /// Base frequency
-    #[arg(short = 'f', long = "BaseFrequency", required = false, value_name = "Float", 
-            default_value = "440.0", hide_default_value = true)]
-    freq: f64,
+    /// Base frequency, or a comma separated list with one per channel
+    #[arg(short = 'f', long = "BaseFrequency", required = false, value_name = "Float",
+            default_value = "440.0", hide_default_value = true, value_delimiter = ',')]
+    freq: Vec<f64>,
 
     /// Minimum frequency
-    #[arg(long = "MinFrequency", required = false, value_name = "Float", 
+    #[arg(long = "MinFrequency", required = false, value_name = "Float",
             default_value = "20.0", hide_default_value = true)]
     freq_min: f64,
 
     /// Maximum frequency
-    #[arg(long = "MaxFrequency", required = false, value_name = "Float", 
+    #[arg(long = "MaxFrequency", required = false, value_name = "Float",
             default_value = "20000.0", hide_default_value = true)]
     freq_max: f64,
 
     /// Time offset in seconds
     #[arg(short = 'o', long = "Offset", required = false, value_name = "Float",
-            allow_hyphen_values = true, number_of_values = 1, default_value = "0.0", 
+            allow_hyphen_values = true, number_of_values = 1, default_value = "0.0",
             hide_default_value = true)]
     offset: f64,
 
-    /// Generator-Specific Parameter 1
-    #[arg(short = 'p', long = "p1", required = false, value_name = "Float",  
-            default_value = "1.0", hide_default_value = true)]
-    param_1: f64,
+    /// Generator-Specific Parameter 1, or a comma separated list with one per channel
+    #[arg(short = 'p', long = "p1", required = false, value_name = "Float",
+            default_value = "1.0", hide_default_value = true, value_delimiter = ',')]
+    param_1: Vec<f64>,
 
-    /// Generator-Specific Parameter 1 as dBFS value
-    #[arg(long = "p1dB", required = false, value_name = "Float", allow_hyphen_values = true,  
-            number_of_values = 1, default_value = "0.0", hide_default_value = true)]
-    param_1_db: f64,
+    /// Generator-Specific Parameter 1 as dBFS value, or a comma separated list
+    #[arg(long = "p1dB", required = false, value_name = "Float", allow_hyphen_values = true,
+            number_of_values = 1, default_value = "0.0", hide_default_value = true,
+            value_delimiter = ',')]
+    param_1_db: Vec<f64>,
 
-    /// Generator-Specific Parameter 2
-    #[arg(long = "p2", required = false, value_name = "Float",  
-            default_value = "1.0", hide_default_value = true)]
-    param_2: f64,
+    /// Generator-Specific Parameter 2, or a comma separated list with one per channel
+    #[arg(long = "p2", required = false, value_name = "Float",
+            default_value = "1.0", hide_default_value = true, value_delimiter = ',')]
+    param_2: Vec<f64>,
 
-    /// Generator-Specific Parameter 2 as dBFS value
-    #[arg(long = "p2dB", required = false, value_name = "Float", allow_hyphen_values = true,  
-            number_of_values = 1, default_value = "0.0", hide_default_value = true)]
-    param_2_db: f64,
+    /// Generator-Specific Parameter 2 as dBFS value, or a comma separated list
+    #[arg(long = "p2dB", required = false, value_name = "Float", allow_hyphen_values = true,
+            number_of_values = 1, default_value = "0.0", hide_default_value = true,
+            value_delimiter = ',')]
+    param_2_db: Vec<f64>,
 
-    /// Shape of the gain envelope / "fade window"
-    #[arg(short = 'w', long = "WindowShape", required = false, value_name = "String", 
-            default_value = "def", hide_default_value = true)]
-    window: String,
+    /// Shape of the gain envelope / "fade window", or a comma separated list with one per channel
+    #[arg(short = 'w', long = "WindowShape", required = false, value_name = "String",
+            default_value = "def", hide_default_value = true, value_delimiter = ',')]
+    window: Vec<String>,
 
     /// Modifier for fade window curve shape
-    #[arg(long = "WindowCurve", required = false, value_name = "Float", 
+    #[arg(long = "WindowCurve", required = false, value_name = "Float",
             default_value = "2.0", hide_default_value = true)]
     window_k: f64,
 
-    /// Gain scalar
-    #[arg(short = 'g', long = "Gain", required = false, value_name = "Float", 
-            default_value = "1.0", hide_default_value = true, allow_hyphen_values = true)]
-    gain: f64,
+    /// Gain scalar, or a comma separated list with one per channel
+    #[arg(short = 'g', long = "Gain", required = false, value_name = "Float",
+            default_value = "1.0", hide_default_value = true, allow_hyphen_values = true,
+            value_delimiter = ',')]
+    gain: Vec<f64>,
+
+    /// Gain scalar as dBFS value, or a comma separated list with one per channel
+    #[arg(short = 'G', long = "GaindB", required = false, value_name = "Float",
+            default_value = "0.0", allow_hyphen_values = true, number_of_values = 1,
+            hide_default_value = true, value_delimiter = ',')]
+    gain_db: Vec<f64>,
+
+    /// Seed for all random number generation, chosen at random if not given
+    #[arg(long = "Seed", alias = "seed", required = false, value_name = "Positive Int")]
+    seed: Option<u64>,
+
+    /// Leave the date out of the file, so the same command always writes a bit-identical file
+    #[arg(long = "NoDate")]
+    no_date: bool,
 
-    /// Gain scalar as dBFS value
-    #[arg(short = 'G', long = "GaindB", required = false, value_name = "Float", 
-            default_value = "0.0", allow_hyphen_values = true, number_of_values = 1, 
-            hide_default_value = true)]
-    gain_db: f64,
-    
     /// Display verbose output
     #[arg(short, long)]
     verbose: bool,
 }
 
+// Rendering needs no subcommand, as it's all homados does. These only read back what it wrote.
+#[derive(Subcommand)]
+enum Command {
+    /// Print the format of a WAV or FLAC file, and the parameters it was rendered with
+    Inspect {
+        /// The file to inspect
+        file: String,
+    },
+    /// Render a file again from the parameters stored in it, and check the samples match
+    Reproduce {
+        /// The file to reproduce
+        file: String,
+
+        /// Directory for the new file, next to the original if not given
+        path: Option<String>,
+    },
+}
+
 fn main() {
-    let cli = Cli::parse();
-    let mut spec = hound::WavSpec {
+    let mut cli = Cli::parse();
+    let result = match cli.command.take() {
+        Some(Command::Inspect { file }) => inspect(&file),
+        Some(Command::Reproduce { file, path }) => reproduce(&file, path),
+        None => run(cli, std::env::args().collect()).map(|_| ()),
+    };
+    if let Err(error) = result {
+        eprintln!("\n\n\tError:\t{}\n\n", error);
+        std::process::exit(error.exit_code());
+    }
+}
+
+// Everything is checked and resolved before anything is written, so a bad argument never leaves
+// a file or directory behind. The arguments are the command line the render was parsed from,
+// which is stored in the file, and the path of the file written is given back.
+fn run(cli: Cli, args: Vec<String>) -> Result<String, Error> {
+    // Anything we have to say goes to stderr when the samples themselves are going to stdout.
+    let stdout = cli.stdout;
+    macro_rules! say {
+        ($($arg:tt)*) => {if stdout {eprintln!($($arg)*)} else {println!($($arg)*)}};
+    }
+
+    let mut spec = wav::Spec {
         channels: 1,
         sample_rate: 48000,
         bits_per_sample: 24,
-        sample_format: hound::SampleFormat::Int,
+        sample_format: wav::SampleFormat::Int,
+        layout: None,
     };
 
-    let sound = generator::Sound {
-        sv: [0.0; 8],
-        freq: cli.freq.clone(),
-        freq_min: cli.freq_min.clone(),
-        freq_max: cli.freq_max.clone(),
-        offset: cli.rate.clone() as f64 * cli.offset.clone(),
-        p1: 
-            if cli.param_1_db.clone() != 0.0 {
-                dbfs_to_amp(cli.param_1_db.clone())
-            } else {
-                cli.param_1.clone()
-            },
-        p2: 
-            if cli.param_2_db.clone() != 0.0 {
-                dbfs_to_amp(cli.param_2_db.clone())
-            } else {
-                cli.param_2.clone()
-            },
+    // Without a given seed we pick one ourselves, so even a random render can be reproduced later.
+    let seed = cli.seed.unwrap_or_else(rand::random);
+    if cli.verbose && cli.seed.is_none() {say!("\nNo seed given, using generated seed: {}", seed)}
+
+    // The file format can be given on its own, or as the extension of the file name. The extension
+    // is dropped from the name, as it is added back on once the file is written. RF64 files are
+    // named ".wav" like any other.
+    let (name, named_format) = match Format::from_file_name(&cli.name) {
+        Some((format, stem)) => (stem.to_string(), Some(format)),
+        None => (cli.name.clone(), None),
     };
+    let format = match cli.format.as_deref() {
+        Some(format) => Format::from_name(format).ok_or_else(|| Error::unknown("format", format,
+            FORMATS.iter().flat_map(|(_, names)| names.iter().copied())))?,
+        None => named_format.unwrap_or(Format::Wav),
+    };
+    if named_format.is_some_and(|named| named.extension() != format.extension())
+        {return Err(Error::InvalidParameter(format!("The file name \"{}\" doesn't match the {} format.", cli.name, format.name())))}
+    if cli.stdout && !format.streams()
+        {return Err(Error::UnsupportedFormat(format!("{} output can't be streamed, use wav or raw PCM.", format.name())))}
+    if !cli.stdout && format == Format::Raw
+        {return Err(Error::InvalidParameter(String::from("Raw PCM is only streamed, with --Stdout.")))}
+    if cli.stdout && io::stdout().is_terminal()
+        {return Err(Error::InvalidParameter(String::from("Audio won't be streamed to a terminal, pipe it into another program.")))}
+
+    // A raw PCM name like "s24le" gives the sample format and bit depth, unless they're given too.
+    let encoding = cli.format.as_deref().and_then(Format::raw_encoding);
+
+    // The sample format can be given on its own, or as an "f" suffix on the bit depth. Without a
+    // bit depth, integer output is 24 bit and float output is 32 bit.
+    let bitdepth = cli.bitdepth.as_deref();
+    let float_suffix = bitdepth.is_some_and(|bits| bits.ends_with('f'));
+    let sample_format = match cli.sample_format.as_deref() {
+        Some(name) => wav::SampleFormat::from_name(name).ok_or_else(|| Error::unknown("sample format", name,
+            wav::SAMPLE_FORMATS.iter().flat_map(|(_, names)| names.iter().copied())))?,
+        None if float_suffix => wav::SampleFormat::Float,
+        None => encoding.map(|(sample_format, _)| sample_format).unwrap_or(wav::SampleFormat::Int),
+    };
+    if float_suffix && sample_format == wav::SampleFormat::Int
+        {return Err(Error::InvalidParameter(String::from("A float bit depth was given with the int sample format.")))}
+    let bits_per_sample: u16 = match bitdepth {
+        Some(bits) => bits.trim_end_matches('f').parse()
+            .map_err(|_| Error::InvalidParameter(format!("Unrecognized bit depth \"{}\".", bits)))?,
+        None if encoding.is_some_and(|(encoding_format, _)| encoding_format == sample_format)
+            => encoding.map(|(_, bits)| bits).unwrap_or(24),
+        None if sample_format == wav::SampleFormat::Float => 32,
+        None => 24,
+    };
+    if encoding.is_some_and(|encoding| encoding != (sample_format, bits_per_sample))
+        {return Err(Error::InvalidParameter(format!("{} doesn't match {} bit {} output.",
+            cli.format.as_deref().unwrap_or(""), bits_per_sample, sample_format.name())))}
+    if !sample_format.supports(bits_per_sample)
+        {return Err(Error::UnsupportedFormat(format!("{} bit {} output is not supported, use 8, 16, 24, or 32 for int, and 32 or 64 for float.",
+            bits_per_sample, sample_format.name())))}
+    if cli.clip && sample_format == wav::SampleFormat::Int
+        {say!("\nWARNING: Integer output is always clipped\nThe clip flag has no effect.\n\n")}
+
+    // An RMS target is one more kind of normalization, so only one of the two can be given.
+    if cli.normalize.is_some() && cli.rms.is_some()
+        {return Err(Error::InvalidParameter(String::from("Only one of --Normalize and --RMS can be given.")))}
+    let normalize_arg = cli.normalize.clone().or(cli.rms.as_ref().map(|rms| format!("rms={}", rms)));
+    let mut normalize = normalize_arg.as_deref().map(|arg| Normalize::from_arg(arg).ok_or_else(|| Error::InvalidParameter(
+        format!("Unrecognized normalization \"{}\", use peak=<dBFS>, lufs=<LUFS>, or rms=<dBFS>.", arg)))).transpose()?;
+    match &mut normalize {
+        Some(Normalize::Rms { band, aes17, .. }) => {
+            if cli.rms_band {*band = Some((cli.freq_min, cli.freq_max))}
+            *aes17 = cli.aes17;
+        }
+        _ if cli.rms_band || cli.aes17
+            => say!("\nWARNING: The RMS band and AES17 options only apply to an RMS target\nThey will be ignored.\n\n"),
+        _ => {}
+    }
 
-    // We only need to pass in one gain scalar. If both unit types are provided, dB will triumph.
-    let mut gain = cli.gain;
-    if cli.gain_db != 0.0 {gain = dbfs_to_amp(cli.gain_db)};
+    let true_peak_limit: Option<f64> = cli.true_peak_limit.as_deref().map(|limit| limit.trim_end_matches("dBTP")
+        .parse().map_err(|_| Error::InvalidParameter(format!("Unrecognized true peak limit \"{}\", use a value in dBTP.", limit))))
+        .transpose()?;
+
+    // Dither and noise shaping only come into play when quantizing to integer samples.
+    let dither = Dither::from_name(&cli.dither).ok_or_else(|| Error::unknown("dither type", &cli.dither,
+        DITHERS.iter().flat_map(|(_, names)| names.iter().copied())))?;
+    let noise_shape = NoiseShape::from_name(&cli.noise_shaping).ok_or_else(|| Error::unknown("noise shaping type",
+        &cli.noise_shaping, NOISE_SHAPES.iter().flat_map(|(_, names)| names.iter().copied())))?;
+    if sample_format == wav::SampleFormat::Float && (dither != Dither::None || noise_shape != NoiseShape::None)
+        {say!("\nWARNING: Dither and noise shaping only apply to integer output\nThey will be ignored.\n\n")}
+    if noise_shape == NoiseShape::FWeighted && cli.rate != 44100 && cli.rate != 48000
+        {say!("\nWARNING: F-weighted noise shaping is designed for 44.1kHz\nIts curve will be shifted at other rates.\n\n")}
+
+    // A layout sets the channel count, so a count given alongside it has to agree.
+    let layout = cli.layout.as_deref().map(|name| wav::Layout::from_name(name).ok_or_else(|| Error::unknown("layout",
+        name, wav::LAYOUTS.iter().flat_map(|(_, names)| names.iter().copied())))).transpose()?;
+    let channel_count = layout.map(|layout| layout.channels()).unwrap_or(cli.channels);
+    if cli.channels != 1 && cli.channels != channel_count
+        {return Err(Error::InvalidParameter(format!("{} channels were given, but the layout has {}.", cli.channels, channel_count)))}
+    if channel_count == 0
+        {return Err(Error::InvalidParameter(String::from("The channel count must be at least 1.")))}
+    if cli.rate == 0
+        {return Err(Error::InvalidParameter(String::from("The sample rate must be above 0.")))}
+
+    // Resolve the sound and window types once, before anything is written to disk.
+    let registry = Registry::default();
+    // Correlated noise builds on independent channels, so that's our default when it's asked for.
+    let default_mode = if cli.correlation.is_some() {"independent"} else {"duplicate"};
+    let channel_mode_name = cli.channel_mode.as_deref().unwrap_or(default_mode);
+    let channel_mode = generator::ChannelMode::from_name(channel_mode_name).ok_or_else(|| Error::unknown("channel mode",
+        channel_mode_name, generator::CHANNEL_MODES.iter().flat_map(|(_, names)| names.iter().copied())))?;
+    let correlation = cli.correlation.unwrap_or(0.0);
+    if !(0.0..=1.0).contains(&correlation)
+        {return Err(Error::InvalidParameter(String::from("Correlation must be between 0.0 and 1.0.")))}
+    let correlated = cli.correlation.is_some() && channel_mode == generator::ChannelMode::Independent
+        && channel_count > 1;
+
+    // Per-channel arguments may hold more values than there are channels, the extras go unused.
+    let list_lengths = [cli.sound_type.len(), cli.freq.len(), cli.param_1.len(), cli.param_1_db.len(),
+        cli.param_2.len(), cli.param_2_db.len(), cli.window.len(), cli.gain.len(), cli.gain_db.len()];
+    if list_lengths.iter().any(|length| *length > channel_count as usize)
+        {say!("\nWARNING: More per-channel values given than channels\nThe extras will be ignored.\n\n")}
+
+    // Every channel gets its own generator, window, and gain. Duplicated channels share a seed
+    // and so render the same signal, independent channels get their own seed, and first-only
+    // leaves the rest silent.
+    let mut channels: Vec<generator::Channel> = Vec::new();
+    let mut channel_params: Vec<generator::Params> = Vec::new();
+    let mut any_correlated = false;
+    let (mut loud, mut flipped, mut loud_db) = (false, false, false);
+    for channel in 0..channel_count {
+        let param_1_db = per_channel(&cli.param_1_db, channel);
+        let param_2_db = per_channel(&cli.param_2_db, channel);
+        let params = generator::Params {
+            freq: per_channel(&cli.freq, channel),
+            freq_min: cli.freq_min,
+            freq_max: cli.freq_max,
+            offset: cli.rate as f64 * cli.offset,
+            p1:
+                if param_1_db != 0.0 {
+                    dbfs_to_amp(param_1_db)
+                } else {
+                    per_channel(&cli.param_1, channel)
+                },
+            p2:
+                if param_2_db != 0.0 {
+                    dbfs_to_amp(param_2_db)
+                } else {
+                    per_channel(&cli.param_2, channel)
+                },
+            seed: channel_mode.channel_seed(seed, channel),
+        };
+        let sound_type =
+            if channel > 0 && channel_mode == generator::ChannelMode::FirstOnly {
+                String::from("silence")
+            } else {
+                per_channel(&cli.sound_type, channel)
+            };
+        let sound_names = || registry.generator_names().into_iter().flatten().map(|name| name.as_str());
+        let mut sound = registry.generator(&sound_type, &params)
+            .ok_or_else(|| Error::unknown("sound type", &sound_type, sound_names()))?;
+
+        // With correlated noise every channel also carries a share of one source common to all
+        // channels of the same type.
+        let is_noise = generator::SoundType::from_name(&sound_type).is_some_and(|kind| kind.is_noise());
+        if correlated && is_noise {
+            let common_params = generator::Params {
+                seed: generator::Correlated::common_seed(seed),
+                ..params
+            };
+            let common = registry.generator(&sound_type, &common_params)
+                .ok_or_else(|| Error::unknown("sound type", &sound_type, sound_names()))?;
+            sound = Box::new(generator::Correlated::new(common, sound, correlation));
+            any_correlated = true;
+        }
+
+        let window_type = per_channel(&cli.window, channel);
+        let window = registry.envelope(&window_type, cli.window_k).ok_or_else(|| Error::unknown("window type",
+            &window_type, registry.envelope_names().into_iter().flatten().map(|name| name.as_str())))?;
+
+        // We only need to pass in one gain scalar. If both unit types are provided, dB will triumph.
+        let gain_db = per_channel(&cli.gain_db, channel);
+        let mut gain = per_channel(&cli.gain, channel);
+        if gain_db != 0.0 {gain = dbfs_to_amp(gain_db)};
+        loud |= gain.abs() > 1.0 && gain_db == 0.0;
+        flipped |= gain < 0.0 && gain_db == 0.0;
+        loud_db |= gain_db > 0.0;
+
+        channels.push(generator::Channel { sound, window, gain });
+        channel_params.push(params);
+    }
+    if cli.correlation.is_some() && !any_correlated
+        {say!("\nWARNING: Correlation only applies to noise on independent channels\nIt will be ignored.\n\n")}
 
     // Scaling the volume should always be possible -- regardless of the window shape.
     // Warn the user if the given gain value may cause any issues.
-    if gain.abs() > 1.0 && cli.gain_db == 0.0
-        {println!("\nWARNING: |Scalar| > 1.0 Amplitude\nThis may cause the output to clip.\n\n")}
-    if gain < 0.0 && cli.gain_db == 0.0
-        {println!("\nWARNING: Scalar < 0.0 Amplitude\nThis will flip the signal polarity\n\n")}
-    if cli.gain_db > 0.0 
-        {println!("\nWARNING: Scalar > 0.0dBFS\nThis may cause the output to clip.\n\n")}
+    // Float output without clipping is free to go past full scale, rather than clip.
+    let over = if cli.clip || sample_format == wav::SampleFormat::Int {"clip"} else {"exceed full scale"};
+    if loud
+        {say!("\nWARNING: |Scalar| > 1.0 Amplitude\nThis may cause the output to {}.\n\n", over)}
+    if flipped
+        {say!("\nWARNING: Scalar < 0.0 Amplitude\nThis will flip the signal polarity\n\n")}
+    if loud_db
+        {say!("\nWARNING: Scalar > 0.0dBFS\nThis may cause the output to {}.\n\n", over)}
 
     // We only need to pass in one duration. If both unit types are provided, samples triumphs.
     let mut duration = cli.duration_samples;
     if cli.duration_samples == 480000.0 && cli.duration_seconds != 10.0 {
         duration = cli.duration_seconds * cli.rate as f64;
     }
+    if duration.is_nan() || duration < 0.0
+        {return Err(Error::InvalidParameter(String::from("The duration must be 0 or more.")))}
+    if duration.is_infinite() && !cli.stdout
+        {return Err(Error::InvalidParameter(String::from("An endless duration can only be streamed, with --Stdout.")))}
+    if duration.is_infinite() && normalize.is_some()
+        {return Err(Error::InvalidParameter(String::from("An endless render can't be measured to normalize it.")))}
+    // A loop repeats whole cycles of the base frequency, so every channel has to be periodic at the
+    // same frequency, or silent. It starts where the first of them crosses zero.
+    let mut looped: Option<Loop> = None;
+    if cli.looped && format.container().is_none() && format != Format::Aiff
+        {say!("\nWARNING: Loops are only marked in WAV and AIFF files\nThe loop flag will be ignored.\n\n")}
+    else if cli.looped {
+        let periodic: Vec<Option<(f64, f64)>> = channels.iter().zip(channel_params.iter()).filter_map(|(channel, params)| {
+            match generator::SoundType::from_name(&channel.sound.name()) {
+                Some(generator::SoundType::Silence) => None,
+                Some(kind) if kind.is_periodic() => Some(Some((params.freq, kind.zero_crossing()))),
+                _ => Some(None),
+            }
+        }).collect();
+        match periodic.first() {
+            Some(Some((freq, zero_crossing))) if periodic.iter().all(|other| other.is_some_and(|(other, _)| other == *freq)) => {
+                looped = Loop::whole_cycles(*freq, cli.rate, duration as u64, *zero_crossing);
+                if looped.is_none()
+                    {say!("\nWARNING: The duration is shorter than one cycle\nNo loop will be marked.\n\n")}
+            }
+            _ => say!("\nWARNING: Loops need a periodic sound at one frequency on every channel\nNo loop will be marked.\n\n"),
+        }
+    }
+    // Markers go where a sweep passes each value asked for, and where each window reaches the
+    // middle of a fade, on any channel. Channels marked at the same points share their markers.
+    let mut marker_sets: Vec<MarkerSet> = Vec::new();
+    let mut marker_values: Vec<f64> = Vec::new();
+    for name in &cli.markers {
+        match (MarkerSet::from_name(name), name.parse::<f64>()) {
+            (Some(set), _) => marker_sets.push(set),
+            (None, Ok(value)) if value.is_finite() => marker_values.push(value),
+            _ => return Err(Error::unknown("marker", name, MARKER_SETS.iter().flat_map(|(_, names)| names.iter().copied()))),
+        }
+    }
+    let mut markers: Vec<Marker> = Vec::new();
+    if !cli.markers.is_empty() && format.container().is_none()
+        {say!("\nWARNING: Markers are only written in WAV files\nThe markers will be ignored.\n\n")}
+    else if !cli.markers.is_empty() && duration.is_infinite()
+        {say!("\nWARNING: An endless render can't be marked\nThe markers will be ignored.\n\n")}
+    else if !cli.markers.is_empty() {
+        let last = (duration as u64).saturating_sub(1) as f64;
+        let mut mark = |position: f64, label: String| if position * last <= u32::MAX as f64
+            {markers.push(Marker { position: (position * last).round() as u32, label })};
+        for channel in &channels {
+            if let Some(sweep) = channel.sound.sweep() {
+                let mut values = marker_values.clone();
+                if let generator::Sweep::Frequency { from, to, .. } = sweep {
+                    values.extend(marker_sets.iter().flat_map(|set| set.frequencies(from.min(to), from.max(to))));
+                }
+                for value in values {
+                    if let Some(position) = sweep.position(value) {mark(position, sweep.label(value))}
+                }
+            }
+            if marker_sets.contains(&MarkerSet::Window) {
+                for (position, label) in channel.window.markers() {mark(position, label)}
+            }
+        }
+        markers.sort();
+        markers.dedup();
+        if markers.is_empty()
+            {say!("\nWARNING: Nothing asked to be marked is in the render\nNo markers will be written.\n\n")}
+    }
+    if layout.is_some() && format == Format::Aiff
+        {say!("\nWARNING: AIFF files can't hold a speaker layout\nChannels are written in the layout's WAV order.\n\n")}
+
+    if cli.path.is_empty() || name.is_empty()
+        {return Err(Error::InvalidParameter(String::from("The output path and name can't be empty.")))}
+    if args.iter().any(|arg| arg.contains(['\n', '\r']))
+        {return Err(Error::InvalidParameter(String::from("Arguments can't hold line breaks, as each tag of the file is one line.")))}
+
+    // Populate the sound specifications to pass to the generator
+    spec.channels = channel_count;
+    spec.layout = layout;
+    spec.bits_per_sample = bits_per_sample;
+    spec.sample_format = sample_format;
+    let quantizer = Quantizer::new(dither, noise_shape, bits_per_sample, channel_count, generator::Sound::dither_rng(seed));
+    spec.sample_rate = cli.rate;
+    format.supports(&spec).map_err(Error::UnsupportedFormat)?;
 
     // We will create the directory for our output in case it does not already exist.
-    if Path::new(cli.path.clone().as_str()).exists() == false {
-        fs::create_dir_all(cli.path.clone()).expect("\n\nError occurred creating output\n\n");
+    if !cli.stdout && !Path::new(cli.path.as_str()).exists() {
+        fs::create_dir_all(&cli.path).map_err(|e| Error::io(&cli.path, e))?;
     }
 
     // Ensure that our output path distinguishes the file name from its target directory
     let mut output_path: String = cli.path.clone();
-    if cli.path.chars().last().unwrap() == '/' {output_path += &cli.name}
-    else {output_path += &("/".to_owned() + &cli.name)}
+    if cli.path.ends_with('/') {output_path += &name}
+    else {output_path += &("/".to_owned() + &name)}
+
+    // Describe the render in the file itself, for the formats that can hold it. Per-channel values
+    // are listed the way they're given as arguments, as a single value when every channel agrees.
+    let command: Vec<String> = std::iter::once(String::from("homados")).chain(args.into_iter().skip(1))
+        .map(|arg| quote_arg(&arg)).collect();
+    let list = |values: Vec<String>| if values.iter().all(|value| *value == values[0]) {values[0].clone()} else {values.join(",")};
+    let mut tags: Vec<(&str, String)> = vec![
+        ("TITLE", name.clone()),
+        ("ENCODER", format!("homados {}", env!("CARGO_PKG_VERSION"))),
+        ("COMMAND", command.join(" ")),
+        ("SEED", seed.to_string()),
+        ("DURATION_SAMPLES", (duration as u64).to_string()),
+        ("SOUND_TYPE", list(channels.iter().map(|channel| channel.sound.name()).collect())),
+        ("BASE_FREQUENCY", list(channel_params.iter().map(|params| params.freq.to_string()).collect())),
+        ("MIN_FREQUENCY", cli.freq_min.to_string()),
+        ("MAX_FREQUENCY", cli.freq_max.to_string()),
+        ("OFFSET", cli.offset.to_string()),
+        ("P1", list(channel_params.iter().map(|params| params.p1.to_string()).collect())),
+        ("P2", list(channel_params.iter().map(|params| params.p2.to_string()).collect())),
+        ("WINDOW", list(channels.iter().map(|channel| channel.window.name()).collect())),
+        ("WINDOW_CURVE", cli.window_k.to_string()),
+        ("GAIN", list(channels.iter().map(|channel| channel.gain.to_string()).collect())),
+        ("CHANNEL_MODE", channel_mode.name().to_string()),
+    ];
+    // The date is all that tells two renders of the same command apart, so it can be left out.
+    if !cli.no_date {tags.insert(2, ("DATE", utc_now()))}
+    if let Some(normalize) = normalize_arg {tags.push(("NORMALIZE", normalize))}
+    if let Some(limit) = true_peak_limit {tags.push(("TRUE_PEAK_LIMIT", format!("{}dBTP", limit)))}
+
+    // The offset also places the render on a timeline, for the formats that keep a time reference.
+    let metadata = Metadata {
+        tags: tags.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        looped,
+        time_reference: (cli.offset * cli.rate as f64).max(0.0) as u64,
+        markers,
+    };
+    let format = format.for_length(&spec, &metadata, (!duration.is_infinite()).then_some(duration as u64))
+        .map_err(Error::UnsupportedFormat)?;
 
-    // Populate the sound specifications to pass to the generator
-    spec.channels = cli.channels;
-    spec.bits_per_sample = cli.bitdepth;
-    spec.sample_rate = cli.rate;
-    
     // Now call the appropriate sound generating function.
-    generator::create_sound(output_path, spec, sound, &cli.sound_type, duration, &cli.window, cli.window_k, gain, cli.verbose);
-}
\ No newline at end of file
+    let output = generator::Output {
+        path: output_path,
+        stdout: cli.stdout,
+        format,
+        spec,
+        quantizer,
+        normalize,
+        true_peak_limit,
+        clip: cli.clip,
+        strict: cli.strict,
+        verbose: cli.verbose,
+        metadata,
+    };
+    generator::create_sound(output, &mut channels, duration)
+}
+
+// Print what a file says about itself: its format, laid out as in the verbose output, and the
+// parameters it was rendered with.
+fn inspect(file: &str) -> Result<(), Error> {
+    let info = inspect::read(file)?;
+    println!("\nFile: \t\t{}", file);
+    println!("Format: \t{}", info.format.name());
+    println!("Channels: \t{}", info.channels);
+    println!("Sample Rate: \t{}", info.sample_rate);
+    println!("Bit Depth: \t{}", info.bits_per_sample);
+    println!("Sample Format: \t{}", info.sample_format.name());
+    println!("Duration:\n    Seconds:    {}\n    Frames:     {}",
+        info.frames as f64 / info.sample_rate.max(1) as f64, info.frames);
+    if info.tags.is_empty() {
+        println!("\nNo render parameters are stored in this file.\n");
+        return Ok(());
+    }
+    println!("Parameters:");
+    for (name, value) in &info.tags {
+        println!("    {:<17}{}", name, value);
+    }
+    Ok(())
+}
+
+// Render a file again from the command stored in it, and check the samples come out the same. The
+// command is parsed just as it was given, but writes a file next to the original, or into the
+// given directory, and uses the stored seed when it didn't give one. Defaults are only the same
+// when the version of homados is, so a file from another version comes with a warning.
+fn reproduce(file: &str, path: Option<String>) -> Result<(), Error> {
+    let info = inspect::read(file)?;
+    let command = info.tag("COMMAND").ok_or_else(|| Error::unreadable(file, "it holds no homados command"))?;
+    let encoder = format!("homados {}", env!("CARGO_PKG_VERSION"));
+    if let Some(written) = info.tag("ENCODER").filter(|written| *written != encoder) {
+        println!("\nWARNING: The file was written by {}, and this is {}\nThe samples may not match.\n\n", written, encoder);
+    }
+
+    let parse_error = |e: clap::Error| Error::InvalidParameter(format!("The stored command can't be parsed: {}.", e.kind()));
+    let mut args: Vec<String> = split_command(command).into_iter().filter(|arg| arg != "--Stdout").collect();
+    let matches = Cli::command().try_get_matches_from(&args).map_err(parse_error)?;
+    if matches.value_source("seed") != Some(ValueSource::CommandLine) {
+        let seed = info.tag("SEED").ok_or_else(|| Error::unreadable(file, "it holds no seed"))?;
+        args.extend([String::from("--Seed"), seed.to_string()]);
+    }
+
+    // Only the path and name are changed from what was parsed, so the new file doesn't land on the
+    // original. The stored command is kept as it is, as it still renders the same samples.
+    let original = Path::new(file);
+    let mut cli = Cli::try_parse_from(&args).map_err(parse_error)?;
+    cli.path = path.unwrap_or_else(|| original.parent().map(|parent| parent.to_string_lossy().to_string())
+        .filter(|parent| !parent.is_empty()).unwrap_or_else(|| String::from(".")));
+    let stem = original.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    cli.name = match original.extension() {
+        Some(extension) => format!("{} (reproduced).{}", stem, extension.to_string_lossy()),
+        None => format!("{} (reproduced)", stem),
+    };
+
+    let reproduced = run(cli, args)?;
+    println!("\nFile reproduced at:\nfile://{}", reproduced);
+    let other = inspect::read(&reproduced)?;
+    match inspect::same_samples(file, &info, &reproduced, &other)? {
+        Some(true) => println!("The samples are bit-identical to the original.\n"),
+        Some(false) => println!("\nWARNING: The samples differ from the original\nThe render can't be reproduced exactly.\n\n"),
+        None => println!("\nWARNING: The files are in different formats\nTheir samples can't be compared.\n\n"),
+    }
+    Ok(())
+}
+
+// Quote an argument for the stored command, if it is empty or holds spaces, quotes, or backslashes.
+// Within the quotes, a quote or backslash is escaped with a backslash.
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        return arg.to_string();
+    }
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Split a stored command back into its arguments, undoing quote_arg
+fn split_command(command: &str) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    let (mut arg, mut started, mut quoted) = (String::new(), false, false);
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => (started, quoted) = (true, !quoted),
+            '\\' if quoted => arg.extend(chars.next()),
+            c if c.is_whitespace() && !quoted => {
+                if started {args.push(std::mem::take(&mut arg))}
+                started = false;
+            }
+            c => {
+                arg.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {args.push(arg)}
+    args
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A directory of the test's own, emptied first
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("homados-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    // Render a file named "out" into a directory, just as the command line would
+    fn render(dir: &Path, args: &[&str]) -> String {
+        render_named(dir, "out", args).unwrap()
+    }
+
+    fn render_named(dir: &Path, name: &str, args: &[&str]) -> Result<String, Error> {
+        let args: Vec<String> = ["homados", dir.to_str().unwrap(), name].iter().chain(args).map(|arg| arg.to_string()).collect();
+        run(Cli::try_parse_from(&args).unwrap(), args)
+    }
+
+    // The chunks of a RIFF file, in order
+    fn chunks(file: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let mut chunks = Vec::new();
+        let mut at = 12;
+        while at + 8 <= file.len() {
+            let len = u32::from_le_bytes(file[at + 4 .. at + 8].try_into().unwrap()) as usize;
+            chunks.push((file[at .. at + 4].try_into().unwrap(), &file[at + 8 .. at + 8 + len]));
+            at += 8 + len + len % 2;
+        }
+        chunks
+    }
+
+    fn text(field: &[u8]) -> &str {
+        std::str::from_utf8(field).unwrap().trim_end_matches('\0')
+    }
+
+    #[test]
+    fn utc_dates() {
+        assert_eq!(utc_date(0), "1970-01-01T00:00:00Z");
+        assert_eq!(utc_date(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(utc_date(1709251199), "2024-02-29T23:59:59Z");
+        assert_eq!(utc_date(4102444799), "2099-12-31T23:59:59Z");
+    }
+
+    // The bext chunk is laid out as EBU Tech 3285 has it: a 256 byte description, 32 byte originator
+    // and reference, 10 byte date, 8 byte time, 64 bit time reference, and version 1
+    #[test]
+    fn bext_and_info() {
+        let dir = test_dir("bext");
+        let file = fs::read(render(&dir, &["-t", "sine", "-d", "0.1", "--Offset", "1.5", "--Seed", "5"])).unwrap();
+        let chunks = chunks(&file);
+        let (_, bext) = chunks.iter().find(|(id, _)| id == b"bext").unwrap();
+        let (_, info) = chunks.iter().find(|(id, body)| id == b"LIST" && body.starts_with(b"INFO")).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        let command = format!("homados {} out -t sine -d 0.1 --Offset 1.5 --Seed 5", dir.to_str().unwrap());
+        assert_eq!(text(&bext[..256]), command);
+        assert_eq!(text(&bext[256..288]), format!("homados {}", env!("CARGO_PKG_VERSION")));
+        assert_eq!(text(&bext[288..320]), "");
+        let (date, time) = (text(&bext[320..330]), text(&bext[330..338]));
+        assert_eq!((date.len(), &date[4..5], &date[7..8], time.len(), &time[2..3], &time[5..6]), (10, "-", "-", 8, ":", ":"));
+        assert_eq!(u64::from_le_bytes(bext[338..346].try_into().unwrap()), 72000);
+        assert_eq!(u16::from_le_bytes(bext[346..348].try_into().unwrap()), 1);
+        assert!(bext[348..602].iter().all(|&byte| byte == 0));
+        assert!(text(&bext[602..]).starts_with("A=PCM,F=48000,W=24,M=mono,T=homados"));
+
+        let mut fields: Vec<([u8; 4], &str)> = Vec::new();
+        let mut at = 4;
+        while at + 8 <= info.len() {
+            let len = u32::from_le_bytes(info[at + 4 .. at + 8].try_into().unwrap()) as usize;
+            fields.push((info[at .. at + 4].try_into().unwrap(), text(&info[at + 8 .. at + 8 + len])));
+            at += 8 + len + len % 2;
+        }
+        let field = |id: &[u8; 4]| fields.iter().find(|(field, _)| field == id).unwrap().1;
+        assert_eq!(field(b"INAM"), "out");
+        assert_eq!(field(b"ISFT"), format!("homados {}", env!("CARGO_PKG_VERSION")));
+        assert_eq!(field(b"ICRD"), date);
+        let comment: Vec<&str> = field(b"ICMT").lines().collect();
+        assert_eq!(comment[..3], ["TITLE=out", &format!("ENCODER=homados {}", env!("CARGO_PKG_VERSION")), &format!("DATE={}T{}Z", date, time)]);
+        for tag in [&format!("COMMAND={}", command), "SEED=5", "SOUND_TYPE=sine", "OFFSET=1.5", "DURATION_SAMPLES=4800"] {
+            assert!(comment.contains(&tag), "{} in {:?}", tag, comment);
+        }
+    }
+
+    // Without its date, a render is the same file every time, down to the last byte
+    #[test]
+    fn no_date_is_bit_identical() {
+        let dir = test_dir("no-date");
+        let args = ["-t", "pink", "-d", "0.1", "--Seed", "5", "--NoDate"];
+        let (first, second) = (render(&dir, &args), render(&dir, &args));
+        assert_ne!(first, second);
+        let (first, second) = (fs::read(first).unwrap(), fs::read(second).unwrap());
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(first, second);
+        let (_, bext) = chunks(&first).into_iter().find(|(id, _)| id == b"bext").unwrap();
+        assert!(bext[320..338].iter().all(|&byte| byte == 0));
+    }
+    #[test]
+    fn commands_split_as_they_were_given() {
+        let args: Vec<String> = ["homados", "a dir/", "say \"hi\"", "back\\slash\\", "", "\"", "--Normalize", "peak=-1dBFS", "-t", "pink"]
+            .iter().map(|arg| arg.to_string()).collect();
+        let command: Vec<String> = args.iter().map(|arg| quote_arg(arg)).collect();
+        assert_eq!(command.join(" "), r#"homados "a dir/" "say \"hi\"" "back\\slash\\" "" "\"" --Normalize peak=-1dBFS -t pink"#);
+        assert_eq!(split_command(&command.join(" ")), args);
+    }
+
+    // A line break would split the stored command across two tags, so it's refused up front
+    #[test]
+    fn line_breaks_are_refused() {
+        let dir = test_dir("line-break");
+        assert!(matches!(render_named(&dir, "two\nlines", &["-d", "0.1"]), Err(Error::InvalidParameter(_))));
+        assert!(!dir.exists());
+    }
+
+    // A file can be inspected, then rendered again from what it holds, even with a name that has to
+    // be quoted and escaped, and without a seed given
+    #[test]
+    fn inspect_then_reproduce() {
+        let dir = test_dir("reproduce");
+        for (name, reproduced, args) in [
+                (r#"say "hi" \ there"#, r#"say "hi" \ there (reproduced).wav"#, &["-t", "pink", "-d", "0.1", "-b", "16", "--Dither", "tpdf"][..]),
+                ("sweep.flac", "sweep (reproduced).flac", &["-t", "sweep", "-d", "0.1", "--Normalize", "peak=-3"][..])] {
+            let file = render_named(&dir, name, args).unwrap();
+            inspect(&file).unwrap();
+            reproduce(&file, None).unwrap();
+            let other = dir.join(reproduced).to_str().unwrap().to_string();
+            let (info, other_info) = (inspect::read(&file).unwrap(), inspect::read(&other).unwrap());
+            assert_eq!(inspect::same_samples(&file, &info, &other, &other_info).unwrap(), Some(true), "{}", name);
+            assert_eq!(info.tag("SEED"), other_info.tag("SEED"));
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+    // Strict mode fails a float render that went past full scale just as it does an integer one
+    // that clipped, and leaves no file behind either way
+    #[test]
+    fn strict_fails_past_full_scale() {
+        let dir = test_dir("strict");
+        for bit_depth in ["24", "32f", "64f"] {
+            let result = render_named(&dir, "out", &["-t", "sine", "-d", "0.1", "--Normalize", "peak=3", "-b", bit_depth, "--Strict"]);
+            assert!(matches!(result, Err(Error::Clipped(count)) if count > 1000), "{}", bit_depth);
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        let file = render(&dir, &["-t", "sine", "-d", "0.1", "--Normalize", "peak=3", "-b", "32f"]);
+        assert!(Path::new(&file).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+    // The peak of a normalized file is the target, through windows and gains. Float samples keep it
+    // to within float precision, and 24 bit ones to within a step.
+    #[test]
+    fn normalized_peak() {
+        let dir = test_dir("normalize");
+        for (args, tolerance) in [
+                (&["-t", "pink", "-b", "32f"][..], 1e-7),
+                (&["-t", "sine,pink", "-c", "2", "--ChannelMode", "independent", "-w", "lin_io", "-G", "-12,3"][..], 1.0 / 8388607.0)] {
+            let file = fs::read(render(&dir, &[args, &["-d", "0.5", "--Normalize", "peak=-1"]].concat())).unwrap();
+            let (_, data) = chunks(&file).into_iter().find(|(id, _)| id == b"data").unwrap();
+            let peak = if args.contains(&"32f") {
+                data.chunks(4).map(|sample| f32::from_le_bytes(sample.try_into().unwrap()).abs() as f64).fold(0.0, f64::max)
+            } else {
+                data.chunks(3).map(|sample| (i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) >> 8).abs() as f64 / 8388607.0).fold(0.0, f64::max)
+            };
+            assert!((peak - dbfs_to_amp(-1.0)).abs() <= tolerance, "{:?} peaks at {}", args, peak);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,401 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//          N O R M A L I Z A T I O N          //
+//                                             //
+/////////////////////////////////////////////////
+
+// References:
+// [8] ITU-R BS.1770-4, "Algorithms to measure audio programme loudness and true-peak audio level"
+//  - https://www.itu.int/rec/R-REC-BS.1770
+// [9] EBU Tech 3342, "Loudness Range: A measure to supplement EBU R 128 loudness normalization"
+//  - https://tech.ebu.ch/publications/tech3342
+// [10] The K-weighting filters are redesigned for any sample rate as libebur128 does:
+//  - https://github.com/jiixyj/libebur128
+// [11] AES17, "AES standard method for digital audio engineering - Measurement of digital audio
+//  equipment", where RMS levels are referenced to a full scale sine rather than a full scale square
+// [12] The band filters are Butterworth sections from the Audio EQ Cookbook by Robert Bristow-Johnson
+//  - https://www.w3.org/TR/audio-eq-cookbook/
+
+// Normalizing measures a whole render first, then scales it so the measurement lands on a target.
+// The render is measured as it would be written, with every window and gain applied, but before
+// any clipping or quantizing, so the measurement sees the signal as it really is.
+
+use std::f64::consts::{PI, TAU};
+
+use crate::wav;
+
+// Convert dBFS value to amplitude
+pub fn dbfs_to_amp(input: f64) -> f64 {
+    10.0f64.powf(input / 20.0)
+}
+
+// Convert amplitude to dBFS value
+fn amp_to_dbfs(input: f64) -> f64 {
+    20.0 * input.log10()
+}
+
+// Convert a channel-weighted mean square to loudness in LUFS [8]
+fn power_to_lufs(input: f64) -> f64 {
+    -0.691 + 10.0 * input.log10()
+}
+
+// What a render is normalized to, with its target level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalize {
+    // Highest sample magnitude across all channels, in dBFS
+    Peak(f64),
+    // Integrated loudness across all channels, in LUFS
+    Lufs(f64),
+    // RMS level across all channels in dBFS, optionally only between a low and high frequency,
+    // and optionally sine-referenced per AES17 so that a full scale sine reads 0dBFS [11]
+    Rms { target: f64, band: Option<(f64, f64)>, aes17: bool },
+}
+
+impl Normalize {
+    // Parse a "<kind>=<target>" argument, like "peak=-1dBFS" or "lufs=-23". The unit is optional.
+    pub fn from_arg(arg: &str) -> Option<Normalize> {
+        let (kind, target) = arg.split_once('=')?;
+        let target = target.trim();
+        let target = ["dBFS", "dB", "LUFS", "LKFS"].iter()
+            .find_map(|unit| target.strip_suffix(unit)).unwrap_or(target);
+        let target: f64 = target.trim().parse().ok()?;
+        match kind.trim() {
+            "peak" => Some(Normalize::Peak(target)),
+            "lufs" | "lkfs" | "loudness" => Some(Normalize::Lufs(target)),
+            "rms" => Some(Normalize::Rms { target, band: None, aes17: false }),
+            _ => None,
+        }
+    }
+
+    // The target as shown in verbose output
+    pub fn name(self) -> String {
+        match self {
+            Normalize::Peak(target) => format!("peak {:.1} {}", target, self.unit()),
+            Normalize::Lufs(target) => format!("loudness {:.1} {}", target, self.unit()),
+            Normalize::Rms { target, band, aes17 } => {
+                let mut name = format!("rms {:.1} {}", target, self.unit());
+                if aes17 {name += " (AES17)"}
+                if let Some((low, high)) = band {name += &format!(", {} to {} Hz", low, high)}
+                name
+            }
+        }
+    }
+
+    // The unit of the target and measured levels
+    pub fn unit(self) -> &'static str {
+        match self {
+            Normalize::Peak(_) | Normalize::Rms { .. } => "dBFS",
+            Normalize::Lufs(_) => "LUFS",
+        }
+    }
+
+    // The measured level, in the same unit as the target
+    pub fn measured(self, meter: &Meter) -> f64 {
+        match self {
+            Normalize::Peak(_) => amp_to_dbfs(meter.peak),
+            Normalize::Lufs(_) => meter.integrated(),
+            Normalize::Rms { aes17, .. } => {
+                let rms = amp_to_dbfs(meter.rms());
+                if aes17 {rms + 10.0 * 2.0f64.log10()} else {rms}
+            }
+        }
+    }
+
+    // The gain scalar that brings the measured level to the target. A silent render can't be
+    // brought anywhere, so there is none for it.
+    pub fn gain(self, meter: &Meter) -> Option<f64> {
+        let measured = self.measured(meter);
+        if !measured.is_finite() {return None}
+        match self {
+            Normalize::Peak(target) | Normalize::Lufs(target) | Normalize::Rms { target, .. } => {
+                Some(dbfs_to_amp(target - measured))
+            }
+        }
+    }
+
+    // The band the level is measured in, if it is limited to one
+    pub fn band(self) -> Option<(f64, f64)> {
+        match self {
+            Normalize::Rms { band, .. } => band,
+            _ => None,
+        }
+    }
+}
+
+// A second order IIR filter in direct form I
+#[derive(Clone, Copy, Debug)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    // Butterworth high and low-pass sections [12]. Cascading two with Q = 0.5412 and 1.3066 gives
+    // a fourth order (24dB per octave) Butterworth filter.
+    fn high_pass(f: f64, q: f64, s: f64) -> Biquad {
+        let (cos, alpha) = ((TAU * f / s).cos(), (TAU * f / s).sin() / (2.0 * q));
+        let a0 = 1.0 + alpha;
+        Biquad {
+            b: [(1.0 + cos) / 2.0 / a0, -(1.0 + cos) / a0, (1.0 + cos) / 2.0 / a0],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn low_pass(f: f64, q: f64, s: f64) -> Biquad {
+        let (cos, alpha) = ((TAU * f / s).cos(), (TAU * f / s).sin() / (2.0 * q));
+        let a0 = 1.0 + alpha;
+        Biquad {
+            b: [(1.0 - cos) / 2.0 / a0, (1.0 - cos) / a0, (1.0 - cos) / 2.0 / a0],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0] - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+// The two stages of the K-weighting filter [8]: a high shelf modelling the head, then a high-pass.
+// Their analog prototypes are kept, so the filters match at any sample rate [10].
+fn k_weighting(s: f64) -> [Biquad; 2] {
+    let (f0, g, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / s).tan();
+    let vh = 10.0f64.powf(g / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        x: [0.0; 2],
+        y: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / s).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        x: [0.0; 2],
+        y: [0.0; 2],
+    };
+    [shelf, high_pass]
+}
+
+// A fourth order Butterworth band-pass for RMS measurement. Edges at or past 0Hz or Nyquist are
+// left open, rather than filtered.
+fn band_pass(band: (f64, f64), s: f64) -> Vec<Biquad> {
+    let mut filters = Vec::new();
+    for q in [0.5412, 1.3066] {
+        if band.0 > 0.0 {filters.push(Biquad::high_pass(band.0, q, s))}
+        if band.1 < s / 2.0 {filters.push(Biquad::low_pass(band.1, q, s))}
+    }
+    filters
+}
+
+// Loudness weight of each channel [8]. The LFE isn't counted, and surrounds beside the listener
+// count for +1.5dB. Back speakers are only surrounds when there are no side speakers, as in quad.
+// Files without a layout have no speakers to go by, so all their channels count the same.
+fn channel_weights(spec: &wav::Spec) -> Vec<f64> {
+    let has_sides = spec.channel_mask() & 0x600 != 0;
+    (0..spec.channels).map(|channel| {
+        if spec.layout.is_none() {return 1.0}
+        match spec.speaker_position(channel) {
+            Some(3) => 0.0,
+            Some(9) | Some(10) => 1.41,
+            Some(4) | Some(5) if !has_sides => 1.41,
+            _ => 1.0,
+        }
+    }).collect()
+}
+
+// Takes in a render frame by frame, keeping whatever the normalization needs to know about it.
+// RMS is measured across every sample of every channel, through the band filters if there are any.
+// Loudness is gathered into 100ms steps of K-weighted, channel-weighted energy, which the 400ms
+// momentary and 3s short-term windows are then built from.
+pub struct Meter {
+    peak: f64,
+    energy: f64,
+    samples: u64,
+    bands: Vec<Vec<Biquad>>,
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    step_len: usize,
+    step_energy: f64,
+    step_count: usize,
+    steps: Vec<f64>,
+}
+
+impl Meter {
+    pub fn new(spec: &wav::Spec, band: Option<(f64, f64)>) -> Meter {
+        let s = spec.sample_rate as f64;
+        Meter {
+            peak: 0.0,
+            energy: 0.0,
+            samples: 0,
+            bands: vec![band.map(|band| band_pass(band, s)).unwrap_or_default(); spec.channels as usize],
+            filters: vec![k_weighting(s); spec.channels as usize],
+            weights: channel_weights(spec),
+            step_len: (spec.sample_rate as f64 / 10.0).round() as usize,
+            step_energy: 0.0,
+            step_count: 0,
+            steps: Vec::new(),
+        }
+    }
+
+    // Add one frame, holding one sample per channel
+    pub fn add_frame(&mut self, frame: &[f64]) {
+        for (i, sample) in frame.iter().enumerate() {
+            self.peak = self.peak.max(sample.abs());
+            let banded = self.bands[i].iter_mut().fold(*sample, |x, filter| filter.process(x));
+            self.energy += banded * banded;
+            self.samples += 1;
+
+            let filter = &mut self.filters[i];
+            let shelved = filter[0].process(*sample);
+            let y = filter[1].process(shelved);
+            self.step_energy += self.weights[i] * y * y;
+        }
+        self.step_count += 1;
+        if self.step_count == self.step_len {
+            self.steps.push(self.step_energy);
+            self.step_energy = 0.0;
+            self.step_count = 0;
+        }
+    }
+
+    // RMS amplitude across every sample of every channel
+    pub fn rms(&self) -> f64 {
+        (self.energy / self.samples as f64).sqrt()
+    }
+
+    // Mean square of every window of the given number of steps, hopping one step at a time
+    fn windows(&self, length: usize) -> Vec<f64> {
+        self.steps.windows(length)
+            .map(|window| window.iter().sum::<f64>() / (length * self.step_len) as f64)
+            .collect()
+    }
+
+    // Integrated loudness in LUFS, over 400ms blocks gated at -70 LUFS and then at 10 LU below
+    // the loudness of the blocks that remain [8]
+    pub fn integrated(&self) -> f64 {
+        let blocks: Vec<f64> = self.windows(4).into_iter().filter(|z| power_to_lufs(*z) > -70.0).collect();
+        let relative_gate = power_to_lufs(mean(&blocks)) - 10.0;
+        let blocks: Vec<f64> = blocks.into_iter().filter(|z| power_to_lufs(*z) > relative_gate).collect();
+        power_to_lufs(mean(&blocks))
+    }
+
+    // The loudest 3s short-term loudness in LUFS
+    pub fn short_term_max(&self) -> f64 {
+        power_to_lufs(self.windows(30).into_iter().fold(0.0, f64::max))
+    }
+
+    // Loudness range in LU, the spread between the 10th and 95th percentiles of short-term
+    // loudness, gated at -70 LUFS and then at 20 LU below the loudness of the rest [9]
+    pub fn loudness_range(&self) -> f64 {
+        let windows: Vec<f64> = self.windows(30).into_iter().filter(|z| power_to_lufs(*z) > -70.0).collect();
+        let relative_gate = power_to_lufs(mean(&windows)) - 20.0;
+        let mut loudness: Vec<f64> = windows.into_iter().map(power_to_lufs)
+            .filter(|l| *l > relative_gate).collect();
+        if loudness.is_empty() {return 0.0}
+        loudness.sort_by(f64::total_cmp);
+        let percentile = |p: f64| loudness[((loudness.len() - 1) as f64 * p).round() as usize];
+        percentile(0.95) - percentile(0.10)
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(sample_rate: u32) -> wav::Spec {
+        wav::Spec { channels: 1, sample_rate, bits_per_sample: 24, sample_format: wav::SampleFormat::Int, layout: None }
+    }
+
+    // Measure a mono sine that changes level along the way, given as seconds at a peak level in dBFS
+    fn measure_sine(sample_rate: u32, frequency: f64, band: Option<(f64, f64)>, levels: &[(f64, f64)]) -> Meter {
+        let mut meter = Meter::new(&spec(sample_rate), band);
+        let mut t: u64 = 0;
+        for &(seconds, level) in levels {
+            for _ in 0 .. (seconds * sample_rate as f64) as u64 {
+                let phase = TAU * frequency * t as f64 / sample_rate as f64;
+                meter.add_frame(&[dbfs_to_amp(level) * phase.sin()]);
+                t += 1;
+            }
+        }
+        meter
+    }
+
+    // A full scale 1kHz sine reads -3.01 LUFS on one channel [8], as the K-weighting filter's gain
+    // there is cancelled out by the -0.691 of the loudness formula. A 997Hz sine with an RMS level
+    // of -20dBFS, so peaking 3.01dB higher, reads -20 LUFS, at any sample rate.
+    #[test]
+    fn k_weighted_sine() {
+        for sample_rate in [44100, 48000, 96000] {
+            let full_scale = measure_sine(sample_rate, 997.0, None, &[(5.0, 0.0)]).integrated();
+            assert!((full_scale + 3.01).abs() < 0.05, "{} LUFS at {}Hz", full_scale, sample_rate);
+            let quiet = measure_sine(sample_rate, 997.0, None, &[(5.0, -20.0 + 3.0103)]).integrated();
+            assert!((quiet + 20.0).abs() < 0.05, "{} LUFS at {}Hz", quiet, sample_rate);
+        }
+    }
+
+    // Silence falls under the absolute gate, and anything 10 LU below the rest under the relative
+    // gate, so neither pulls the integrated loudness down
+    #[test]
+    fn loudness_gates() {
+        let alone = measure_sine(48000, 997.0, None, &[(10.0, -20.0)]).integrated();
+        let with_silence = measure_sine(48000, 997.0, None, &[(10.0, -20.0), (10.0, -200.0)]).integrated();
+        let with_quiet = measure_sine(48000, 997.0, None, &[(10.0, -20.0), (10.0, -40.0)]).integrated();
+        assert!((with_silence - alone).abs() < 0.1, "{} against {} LUFS", with_silence, alone);
+        assert!((with_quiet - alone).abs() < 0.1, "{} against {} LUFS", with_quiet, alone);
+        let both = measure_sine(48000, 997.0, None, &[(10.0, -20.0), (10.0, -26.0)]).integrated();
+        assert!(both < alone - 2.0, "{} against {} LUFS", both, alone);
+    }
+
+    // The first and third test signals of [9]: 20s of a 1kHz sine at -20dBFS, then 20s at -30 or
+    // -40dBFS, have a loudness range of 10 and 20 LU, give or take 1
+    #[test]
+    fn loudness_range() {
+        for (quiet, range) in [(-30.0, 10.0), (-40.0, 20.0)] {
+            let meter = measure_sine(48000, 1000.0, None, &[(20.0, -20.0), (20.0, quiet)]);
+            assert!((meter.loudness_range() - range).abs() < 1.0, "{} LU", meter.loudness_range());
+            assert!((meter.short_term_max() + 23.01).abs() < 0.05, "{} LUFS", meter.short_term_max());
+        }
+    }
+
+    // AES17 [11] reads a full scale sine as 0dB, 3.01dB above its plain RMS level. A sine well
+    // outside the band barely registers at all.
+    #[test]
+    fn sine_rms() {
+        let meter = measure_sine(48000, 997.0, None, &[(1.0, 0.0)]);
+        let plain = Normalize::Rms { target: 0.0, band: None, aes17: false }.measured(&meter);
+        let aes17 = Normalize::Rms { target: 0.0, band: None, aes17: true }.measured(&meter);
+        assert!((plain + 3.0103).abs() < 0.001, "{} dBFS", plain);
+        assert!(aes17.abs() < 0.001, "{} dBFS", aes17);
+        let gain = Normalize::Rms { target: -20.0, band: None, aes17: true }.gain(&meter).unwrap();
+        assert!((gain - 0.1).abs() < 1e-4, "{}", gain);
+
+        let band = Some((500.0, 2000.0));
+        let inside = measure_sine(48000, 997.0, band, &[(1.0, 0.0)]);
+        let outside = measure_sine(48000, 100.0, band, &[(1.0, 0.0)]);
+        let rms = Normalize::Rms { target: 0.0, band, aes17: true };
+        assert!(rms.measured(&inside).abs() < 0.1, "{} dBFS", rms.measured(&inside));
+        assert!(rms.measured(&outside) < -40.0, "{} dBFS", rms.measured(&outside));
+    }
+}
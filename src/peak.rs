@@ -0,0 +1,200 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//            T R U E   P E A K S              //
+//                                             //
+/////////////////////////////////////////////////
+
+// References:
+// [8] ITU-R BS.1770-4, Annex 2, "Guidelines for accurate measurement of true-peak level"
+//  - https://www.itu.int/rec/R-REC-BS.1770
+
+// Samples only tell us where the signal is at each sample, not what it does between them. Once it
+// is reconstructed, a square wave or a hard clipped sine can swing well past its highest sample.
+// True peaks are found by oversampling 4x and taking the highest of all the in-between values too.
+
+use std::collections::VecDeque;
+use crate::normalize::dbfs_to_amp;
+
+// The 48 tap interpolation filter of [8], split into its four phases of 12 taps. Each phase gives
+// the signal at one of the four positions between two samples.
+const PHASES: [[f64; 12]; 4] = [
+    [0.0017089843750, 0.0109863281250, -0.0196533203125, 0.0332031250000, -0.0594482421875, 0.1373291015625,
+     0.9721679687500, -0.1022949218750, 0.0476074218750, -0.0266113281250, 0.0148925781250, -0.0083007812500],
+    [-0.0291748046875, 0.0292968750000, -0.0517578125000, 0.0891113281250, -0.1665039062500, 0.4650878906250,
+     0.7797851562500, -0.2003173828125, 0.1015625000000, -0.0582275390625, 0.0330810546875, -0.0189208984375],
+    [-0.0189208984375, 0.0330810546875, -0.0582275390625, 0.1015625000000, -0.2003173828125, 0.7797851562500,
+     0.4650878906250, -0.1665039062500, 0.0891113281250, -0.0517578125000, 0.0292968750000, -0.0291748046875],
+    [-0.0083007812500, 0.0148925781250, -0.0266113281250, 0.0476074218750, -0.1022949218750, 0.9721679687500,
+     0.1373291015625, -0.0594482421875, 0.0332031250000, -0.0196533203125, 0.0109863281250, 0.0017089843750],
+];
+
+// Finds the true peak of each frame as it comes in, and of the whole signal so far
+pub struct TruePeak {
+    history: Vec<[f64; 12]>,
+    pub max: f64,
+}
+
+impl TruePeak {
+    pub fn new(channels: usize) -> TruePeak {
+        TruePeak { history: vec![[0.0; 12]; channels], max: 0.0 }
+    }
+
+    // The highest magnitude of this frame, between its samples and the ones before it
+    pub fn add_frame(&mut self, frame: &[f64]) -> f64 {
+        let mut peak: f64 = 0.0;
+        for (sample, history) in frame.iter().zip(self.history.iter_mut()) {
+            history.rotate_right(1);
+            history[0] = *sample;
+            peak = peak.max(sample.abs());
+            for phase in PHASES.iter() {
+                let value: f64 = phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+                peak = peak.max(value.abs());
+            }
+        }
+        self.max = self.max.max(peak);
+        peak
+    }
+
+    // The true peak of everything so far, in dBTP
+    pub fn max_db(&self) -> f64 {
+        20.0 * self.max.log10()
+    }
+}
+
+// A look-ahead limiter that holds true peaks at or below a ceiling. Every channel gets the same
+// gain, so the image doesn't shift. The gain needed by each frame is held for the look-ahead time,
+// then smoothed across it, so it has always reached its lowest by the time that frame comes out of
+// the delay. The 11 extra frames of delay cover the span of the interpolation filter.
+pub struct Limiter {
+    pub ceiling: f64,
+    detector: TruePeak,
+    delay: VecDeque<Vec<f64>>,
+    needed: VecDeque<f64>,
+    smoothing: VecDeque<f64>,
+    smoothing_sum: f64,
+    released: f64,
+    release: f64,
+    lookahead: usize,
+    pub reduction: f64,
+}
+
+impl Limiter {
+    // Ceiling in dBTP, with a 5ms look-ahead and a 100ms release
+    pub fn new(ceiling: f64, sample_rate: f64, channels: usize) -> Limiter {
+        let lookahead = (0.005 * sample_rate).round().max(1.0) as usize;
+        Limiter {
+            ceiling,
+            detector: TruePeak::new(channels),
+            delay: VecDeque::new(),
+            needed: VecDeque::new(),
+            smoothing: VecDeque::new(),
+            smoothing_sum: 0.0,
+            released: 1.0,
+            release: 1.0 - (-1.0 / (0.1 * sample_rate)).exp(),
+            lookahead,
+            reduction: 1.0,
+        }
+    }
+
+    // How many frames late the limited signal comes out
+    pub fn latency(&self) -> usize {
+        self.lookahead + 11
+    }
+
+    // Take in one frame and give back the one from the start of the delay, once it is full.
+    pub fn process(&mut self, frame: &[f64]) -> Option<Vec<f64>> {
+        let ceiling = dbfs_to_amp(self.ceiling);
+        let peak = self.detector.add_frame(frame);
+        let needed = if peak > ceiling {ceiling / peak} else {1.0};
+
+        // Hold the lowest gain needed across the look-ahead and the filter span
+        self.needed.push_back(needed);
+        if self.needed.len() > self.latency() + 1 {self.needed.pop_front();}
+        let held = self.needed.iter().copied().fold(1.0, f64::min);
+
+        // Drop straight down to the held gain, but recover from it slowly
+        self.released = if held < self.released {held} else {self.released + (held - self.released) * self.release};
+
+        // Then average across the look-ahead, so the gain ramps down instead of stepping
+        self.smoothing.push_back(self.released);
+        self.smoothing_sum += self.released;
+        if self.smoothing.len() > self.lookahead {
+            self.smoothing_sum -= self.smoothing.pop_front().unwrap_or(0.0);
+        }
+        let gain = (self.smoothing_sum / self.lookahead as f64).min(1.0);
+
+        self.delay.push_back(frame.to_vec());
+        if self.delay.len() > self.latency() {
+            self.reduction = self.reduction.min(gain);
+            let mut out = self.delay.pop_front()?;
+            out.iter_mut().for_each(|sample| *sample *= gain);
+            Some(out)
+        } else {
+            None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use std::f64::consts::{FRAC_PI_4, FRAC_PI_2};
+
+    // A sine at a quarter of the sample rate, sampled 45 degrees off its peaks
+    fn quarter_sine(amplitude: f64, frames: usize) -> Vec<f64> {
+        (0 .. frames).map(|n| amplitude * (FRAC_PI_2 * n as f64 + FRAC_PI_4).sin()).collect()
+    }
+
+    // Every sample of this sine is at 0.7071, 3dB below the peaks the signal reaches between them
+    #[test]
+    fn true_peak_between_samples() {
+        let mut meter = TruePeak::new(1);
+        let samples = quarter_sine(1.0, 4800);
+        samples.iter().for_each(|sample| {meter.add_frame(&[*sample]);});
+        let sample_peak = samples.iter().fold(0.0f64, |peak, sample| peak.max(sample.abs()));
+        assert!((20.0 * sample_peak.log10() + 3.01).abs() < 0.01);
+        assert!(meter.max > sample_peak);
+        assert!(meter.max_db().abs() < 0.2, "{} dBTP", meter.max_db());
+    }
+
+    // Run a signal through the limiter and out of its delay, and measure the true peak of the result
+    fn limited(ceiling: f64, samples: &[f64]) -> (Vec<f64>, TruePeak) {
+        let mut limiter = Limiter::new(ceiling, 48000.0, 1);
+        let mut meter = TruePeak::new(1);
+        let flush = vec![0.0; limiter.latency()];
+        let out: Vec<f64> = samples.iter().chain(flush.iter())
+            .filter_map(|sample| limiter.process(&[*sample]))
+            .map(|frame| frame[0])
+            .collect();
+        out.iter().for_each(|sample| {meter.add_frame(&[*sample]);});
+        (out, meter)
+    }
+
+    // Whatever goes in, the true peaks come out at the ceiling and no higher
+    #[test]
+    fn limiter_holds_the_ceiling() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let noise: Vec<f64> = (0 .. 24000).map(|_| rng.random_range(-3.0 .. 3.0)).collect();
+        let mut bursts = vec![0.1; 24000];
+        bursts.iter_mut().enumerate().filter(|(n, _)| n % 4800 < 100).for_each(|(_, sample)| *sample = 2.0);
+        for samples in [quarter_sine(2.0, 24000), noise, bursts] {
+            for ceiling in [-1.0, -6.0] {
+                let (out, meter) = limited(ceiling, &samples);
+                assert_eq!(out.len(), samples.len());
+                assert!(meter.max_db() <= ceiling + 0.01, "{} dBTP over {}", meter.max_db(), ceiling);
+                assert!(meter.max_db() > ceiling - 0.5, "{} dBTP under {}", meter.max_db(), ceiling);
+            }
+        }
+    }
+
+    // A signal already under the ceiling comes out as it went in, only later
+    #[test]
+    fn limiter_passes_quiet_signals() {
+        let samples = quarter_sine(0.5, 4800);
+        let (out, _) = limited(-1.0, &samples);
+        assert_eq!(out, samples);
+    }
+}
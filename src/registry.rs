@@ -0,0 +1,191 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//          T H E   R E G I S T R Y            //
+//                                             //
+/////////////////////////////////////////////////
+
+// The registry maps every sound and window name (and alias) to a constructor, so anything linking
+// homados can look them up the same way the command line does, or add its own alongside ours.
+
+use crate::generator::{Generator, Params, SOUND_TYPES, Sound};
+use crate::window::{DIRECTIONS, Envelope, FLAT_NAMES, SHAPES, Shape, Direction, Window};
+
+pub type GeneratorConstructor = Box<dyn Fn(&Params) -> Box<dyn Generator>>;
+pub type EnvelopeConstructor = Box<dyn Fn(f64) -> Box<dyn Envelope>>;
+
+struct Entry<C> {
+    names: Vec<String>,
+    constructor: C,
+}
+
+pub struct Registry {
+    generators: Vec<Entry<GeneratorConstructor>>,
+    envelopes: Vec<Entry<EnvelopeConstructor>>,
+}
+
+impl Registry {
+    // An empty registry, with none of the built-in sounds or windows
+    pub fn new() -> Registry {
+        Registry { generators: Vec::new(), envelopes: Vec::new() }
+    }
+
+    // Register a generator under the given names. Later registrations win over earlier ones, so
+    // this can also replace a built-in.
+    pub fn register_generator<F>(&mut self, names: &[&str], constructor: F)
+    where F: Fn(&Params) -> Box<dyn Generator> + 'static {
+        self.generators.insert(0, Entry {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            constructor: Box::new(constructor),
+        });
+    }
+
+    // Register an envelope under the given names. The constructor is passed the curve modifier k.
+    pub fn register_envelope<F>(&mut self, names: &[&str], constructor: F)
+    where F: Fn(f64) -> Box<dyn Envelope> + 'static {
+        self.envelopes.insert(0, Entry {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            constructor: Box::new(constructor),
+        });
+    }
+
+    // Build the generator registered under name, if there is one
+    pub fn generator(&self, name: &str, params: &Params) -> Option<Box<dyn Generator>> {
+        self.generators.iter()
+            .find(|entry| entry.names.iter().any(|n| n == name))
+            .map(|entry| (entry.constructor)(params))
+    }
+
+    // Build the envelope registered under name, if there is one
+    pub fn envelope(&self, name: &str, k: f64) -> Option<Box<dyn Envelope>> {
+        self.envelopes.iter()
+            .find(|entry| entry.names.iter().any(|n| n == name))
+            .map(|entry| (entry.constructor)(k))
+    }
+
+    // Every registered generator name, grouped with its aliases
+    pub fn generator_names(&self) -> Vec<&[String]> {
+        self.generators.iter().map(|entry| entry.names.as_slice()).collect()
+    }
+
+    // Every registered envelope name, grouped with its aliases
+    pub fn envelope_names(&self) -> Vec<&[String]> {
+        self.envelopes.iter().map(|entry| entry.names.as_slice()).collect()
+    }
+}
+
+// The default registry holds every built-in sound type and window.
+impl Default for Registry {
+    fn default() -> Registry {
+        let mut registry = Registry::new();
+        for &(kind, names) in SOUND_TYPES.iter().rev() {
+            registry.register_generator(names, move |params| Box::new(Sound::new(kind, params)));
+        }
+        for &(shape, shape_names) in SHAPES.iter().rev() {
+            for &(direction, direction_name) in DIRECTIONS.iter().rev() {
+                let names: Vec<String> = shape_names.iter()
+                    .map(|name| format!("{}_{}", name, direction_name)).collect();
+                let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+                registry.register_envelope(&names, move |k| Box::new(Window::new(shape, direction, k)));
+            }
+        }
+        registry.register_envelope(FLAT_NAMES, |k| Box::new(Window::new(Shape::Flat, Direction::Out, k)));
+        registry
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::SoundType;
+
+    // A generator and envelope of our own, named for telling them apart
+    struct Constant(f64);
+
+    impl Generator for Constant {
+        fn prepare(&mut self, _sample_rate: f64, _duration: u64) {}
+        fn next_sample(&mut self) -> f64 {
+            self.0
+        }
+        fn reset(&mut self) {}
+        fn name(&self) -> String {
+            format!("constant {}", self.0)
+        }
+    }
+
+    impl Envelope for Constant {
+        fn prepare(&mut self, _duration: u64) {}
+        fn gain(&self, _x: f64) -> f64 {
+            self.0
+        }
+        fn name(&self) -> String {
+            format!("constant {}", self.0)
+        }
+    }
+
+    fn params() -> Params {
+        Params { freq: 440.0, freq_min: 20.0, freq_max: 20000.0, offset: 0.0, p1: 1.0, p2: 1.0, seed: 5 }
+    }
+
+    #[test]
+    fn custom_registrations() {
+        let mut registry = Registry::new();
+        assert!(registry.generator("half", &params()).is_none());
+        registry.register_generator(&["half", "one"], |_| Box::new(Constant(0.5)));
+        registry.register_envelope(&["quiet"], |k| Box::new(Constant(k)));
+        let mut half = registry.generator("one", &params()).unwrap();
+        half.prepare(48000.0, 10);
+        assert_eq!((half.name(), half.next_sample()), (String::from("constant 0.5"), 0.5));
+        assert_eq!(registry.envelope("quiet", 0.25).unwrap().gain(3.0), 0.25);
+
+        // A later registration wins for the names it shares, and leaves the others as they were
+        registry.register_generator(&["one"], |_| Box::new(Constant(1.0)));
+        assert_eq!(registry.generator("one", &params()).unwrap().name(), "constant 1");
+        assert_eq!(registry.generator("half", &params()).unwrap().name(), "constant 0.5");
+        assert_eq!(registry.generator_names(), [&[String::from("one")][..], &[String::from("half"), String::from("one")][..]]);
+    }
+
+    // A built-in can be replaced in the default registry too
+    #[test]
+    fn replacing_a_built_in() {
+        let mut registry = Registry::default();
+        registry.register_generator(&["pink"], |_| Box::new(Constant(0.0)));
+        registry.register_envelope(&["flat"], |_| Box::new(Constant(0.5)));
+        assert_eq!(registry.generator("pink", &params()).unwrap().name(), "constant 0");
+        assert_eq!(registry.generator("pink_kellet_econ", &params()).unwrap().name(), "pink_kellet_econ");
+        assert_eq!(registry.envelope("flat", 2.0).unwrap().name(), "constant 0.5");
+        assert_eq!(registry.envelope("def", 2.0).unwrap().name(), "flat");
+    }
+
+    #[test]
+    fn default_registers_every_name() {
+        let registry = Registry::default();
+        let names = registry.generator_names();
+        assert_eq!(names.len(), SOUND_TYPES.len());
+        for (&(kind, aliases), names) in SOUND_TYPES.iter().zip(names) {
+            assert_eq!(names, aliases);
+            for alias in aliases {
+                assert_eq!(registry.generator(alias, &params()).unwrap().name(), kind.name(), "{}", alias);
+                assert_eq!(SoundType::from_name(alias), Some(kind));
+            }
+        }
+
+        let names = registry.envelope_names();
+        assert_eq!(names.len(), SHAPES.len() * DIRECTIONS.len() + 1);
+        for &(shape, shape_names) in SHAPES {
+            for &(direction, direction_name) in DIRECTIONS {
+                let canonical = Window::new(shape, direction, 2.0).name();
+                for shape_name in shape_names {
+                    let name = format!("{}_{}", shape_name, direction_name);
+                    assert_eq!(registry.envelope(&name, 2.0).unwrap().name(), canonical, "{}", name);
+                }
+            }
+        }
+        for name in FLAT_NAMES {
+            assert_eq!(registry.envelope(name, 2.0).unwrap().name(), "flat", "{}", name);
+        }
+        assert!(registry.generator("pinkish", &params()).is_none());
+        assert!(registry.envelope("flatish", 2.0).is_none());
+    }
+}
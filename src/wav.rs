@@ -0,0 +1,640 @@
+           /*      =^..^=       */
+/////////////////////////////////////////////////
+//                                             //
+//          T H E   W A V   W R I T E R        //
+//                                             //
+/////////////////////////////////////////////////
+
+// References:
+// [5] WAVEFORMATEXTENSIBLE, its channel mask, and the default channel assignment are described here:
+//  - https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/ksmedia/ns-ksmedia-waveformatextensible
+//  - https://learn.microsoft.com/en-us/windows/win32/api/mmreg/ns-mmreg-waveformatextensible
+// [17] EBU Tech 3306, "MBWF / RF64: An extended File Format for Audio"
+//  - https://tech.ebu.ch/publications/tech3306
+// [18] Sony Media Software, "Sony Wave64 File Format", which gives the GUID of every chunk
+// [19] EBU Tech 3285, "Specification of the Broadcast Wave Format (BWF)", version 1
+//  - https://tech.ebu.ch/publications/tech3285
+// [20] IBM & Microsoft, "Multimedia Programming Interface and Data Specifications 1.0", 1991, which
+//  defines the LIST chunk and its INFO list, and the cue chunk with its associated data list
+// [21] Microsoft, "New Multimedia Data Types and Data Techniques", revision 3.0, 1994, which defines
+//  the smpl chunk
+
+// A small RIFF WAVE writer of our own. The fmt chunk is the older PCMWAVEFORMAT wherever that is
+// allowed (up to two channels, up to 16 bits, no layout), and WAVEFORMATEXTENSIBLE otherwise, so
+// that every file carries a channel mask telling the reader which speaker each channel feeds.
+// Float files use WAVEFORMATEX, or WAVEFORMATEXTENSIBLE for more than two channels or a layout,
+// plus the fact chunk that every non-PCM format is expected to carry.
+// RIFF sizes are 32 bit, which caps a file at 4GB. Longer files are written as RF64 [17], which is
+// the same layout with a ds64 chunk up front holding 64 bit sizes, or as Sony Wave64 [18], which
+// names every chunk with a GUID and gives every size in 64 bits.
+// Every file says how it was made, in a Broadcast WAV bext chunk [19] and a LIST INFO chunk [20]
+// holding every tag of the render, so a DAW or an archive can show it.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use crate::format::{Loop, Marker, Metadata};
+
+// Speaker positions, in the order their bits appear in dwChannelMask. Channels in a file are always
+// stored in this order, so the n-th set bit of the mask names the n-th channel.
+pub const SPEAKERS: &[(&str, &str)] = &[
+    ("FL", "Front Left"),
+    ("FR", "Front Right"),
+    ("FC", "Front Center"),
+    ("LFE", "Low Frequency Effects"),
+    ("BL", "Back Left"),
+    ("BR", "Back Right"),
+    ("FLC", "Front Left of Center"),
+    ("FRC", "Front Right of Center"),
+    ("BC", "Back Center"),
+    ("SL", "Side Left"),
+    ("SR", "Side Right"),
+    ("TC", "Top Center"),
+    ("TFL", "Top Front Left"),
+    ("TFC", "Top Front Center"),
+    ("TFR", "Top Front Right"),
+    ("TBL", "Top Back Left"),
+    ("TBC", "Top Back Center"),
+    ("TBR", "Top Back Right"),
+];
+
+// The KSDATAFORMAT_SUBTYPE_PCM and _IEEE_FLOAT GUIDs, written as the SubFormat of
+// WAVEFORMATEXTENSIBLE. They only differ in their first byte, which is the old format tag.
+const SUBTYPE_PCM: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+const SUBTYPE_IEEE_FLOAT: [u8; 16] = [
+    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+// The Wave64 GUIDs of each chunk. All but the riff and list chunks share the same last 12 bytes,
+// after the chunk's RIFF name.
+const W64_RIFF: [u8; 16] = [
+    0x72, 0x69, 0x66, 0x66, 0x2e, 0x91, 0xcf, 0x11, 0xa5, 0xd6, 0x28, 0xdb, 0x04, 0xc1, 0x00, 0x00,
+];
+const W64_LIST: [u8; 16] = [
+    0x6c, 0x69, 0x73, 0x74, 0x2f, 0x91, 0xcf, 0x11, 0xa5, 0xd6, 0x28, 0xdb, 0x04, 0xc1, 0x00, 0x00,
+];
+const W64_SUFFIX: [u8; 12] = [0xf3, 0xac, 0xd3, 0x11, 0x8c, 0xd1, 0x00, 0xc0, 0x4f, 0x8e, 0xdb, 0x8a];
+
+// The container a WAV file is written in: plain RIFF, or one of the two with 64 bit sizes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container { Riff, Rf64, Wave64 }
+
+impl Container {
+    // Whether this many frames fit in the container, sizes and all
+    pub fn holds(self, spec: &Spec, metadata: &Metadata, frames: u64) -> bool {
+        let data_len = frames.saturating_mul((spec.bytes_per_sample() * spec.channels) as u64);
+        self.fits(header(spec, self, 0, &chunks(spec, metadata)).len(), data_len)
+    }
+
+    // Whether a data chunk of this length fits after a header of this length
+    fn fits(self, header_len: usize, data_len: u64) -> bool {
+        self != Container::Riff || data_len.saturating_add(header_len as u64 - 8 + data_len % 2) <= u32::MAX as u64
+    }
+
+    // Chunks start on an even byte in RIFF and RF64, and on a multiple of 8 in Wave64
+    pub fn padding(self, len: u64) -> u64 {
+        match self {
+            Container::Riff | Container::Rf64 => len % 2,
+            Container::Wave64 => (8 - len % 8) % 8,
+        }
+    }
+}
+
+// Integer samples are written as PCM at 8, 16, 24, or 32 bits. Float samples are written as IEEE
+// float at 32 or 64 bits, and are never scaled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat { Int, Float }
+
+pub const SAMPLE_FORMATS: &[(SampleFormat, &[&str])] = &[
+    (SampleFormat::Int, &["int", "integer", "pcm"]),
+    (SampleFormat::Float, &["float", "ieee", "ieee_float"]),
+];
+
+impl SampleFormat {
+    pub fn from_name(name: &str) -> Option<SampleFormat> {
+        SAMPLE_FORMATS.iter().find(|(_, names)| names.contains(&name)).map(|(format, _)| *format)
+    }
+
+    pub fn name(&self) -> &'static str {
+        SAMPLE_FORMATS.iter().find(|(format, _)| format == self).map(|(_, names)| names[0]).unwrap_or("custom")
+    }
+
+    // Whether the format can be written at the given bit depth
+    pub fn supports(&self, bits_per_sample: u16) -> bool {
+        match self {
+            SampleFormat::Int => [8, 16, 24, 32].contains(&bits_per_sample),
+            SampleFormat::Float => [32, 64].contains(&bits_per_sample),
+        }
+    }
+}
+
+// Named speaker layouts. Each sets the channel count and the channel mask written to the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Mono, Stereo, Lcr, Quad, Surround50, Surround51, Surround71, Surround714,
+    Ambisonic1, Ambisonic2, Ambisonic3,
+}
+
+// The first name of each entry is its canonical name.
+pub const LAYOUTS: &[(Layout, &[&str])] = &[
+    (Layout::Mono, &["mono", "1.0"]),
+    (Layout::Stereo, &["stereo", "2.0"]),
+    (Layout::Lcr, &["LCR", "lcr", "3.0"]),
+    (Layout::Quad, &["quad", "4.0"]),
+    (Layout::Surround50, &["5.0"]),
+    (Layout::Surround51, &["5.1"]),
+    (Layout::Surround71, &["7.1"]),
+    (Layout::Surround714, &["7.1.4"]),
+    (Layout::Ambisonic1, &["ambisonic", "ambisonic1", "foa", "ambix"]),
+    (Layout::Ambisonic2, &["ambisonic2", "hoa2"]),
+    (Layout::Ambisonic3, &["ambisonic3", "hoa3"]),
+];
+
+impl Layout {
+    pub fn from_name(name: &str) -> Option<Layout> {
+        LAYOUTS.iter().find(|(_, names)| names.contains(&name)).map(|(layout, _)| *layout)
+    }
+
+    pub fn name(&self) -> &'static str {
+        LAYOUTS.iter().find(|(layout, _)| layout == self).map(|(_, names)| names[0]).unwrap_or("custom")
+    }
+
+    // Surround layouts follow the usual film / ffmpeg conventions, with the surrounds of 5.x on the
+    // sides. Ambisonics (ACN channel order, SN3D normalisation) feed no speaker directly, so their
+    // mask is empty.
+    pub fn channel_mask(&self) -> u32 {
+        match self {
+            Layout::Mono => 0x4,
+            Layout::Stereo => 0x3,
+            Layout::Lcr => 0x7,
+            Layout::Quad => 0x33,
+            Layout::Surround50 => 0x607,
+            Layout::Surround51 => 0x60f,
+            Layout::Surround71 => 0x63f,
+            Layout::Surround714 => 0x2d63f,
+            Layout::Ambisonic1 | Layout::Ambisonic2 | Layout::Ambisonic3 => 0,
+        }
+    }
+
+    pub fn channels(&self) -> u16 {
+        match self {
+            Layout::Ambisonic1 => 4,
+            Layout::Ambisonic2 => 9,
+            Layout::Ambisonic3 => 16,
+            _ => self.channel_mask().count_ones() as u16,
+        }
+    }
+}
+
+// Default mask for a file without a layout, assigning channels to speakers in mask order. A single
+// channel feeds the front centre, just as the mono layout does.
+fn default_channel_mask(channels: u16) -> u32 {
+    match channels {
+        1 => Layout::Mono.channel_mask(),
+        _ => ((1u64 << channels.min(SPEAKERS.len() as u16)) - 1) as u32,
+    }
+}
+
+// Everything needed to write the header of a file
+#[derive(Clone, Copy, Debug)]
+pub struct Spec {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    pub sample_format: SampleFormat,
+    pub layout: Option<Layout>,
+}
+
+impl Spec {
+    pub fn channel_mask(&self) -> u32 {
+        self.layout.map(|layout| layout.channel_mask()).unwrap_or(default_channel_mask(self.channels))
+    }
+
+    // Name of the speaker (or ambisonic component) the given channel feeds, for verbose output
+    pub fn speaker(&self, channel: u16) -> String {
+        if let Some(Layout::Ambisonic1 | Layout::Ambisonic2 | Layout::Ambisonic3) = self.layout {
+            return match ["W", "Y", "Z", "X"].get(channel as usize) {
+                Some(component) => format!("Ambisonic {} (ACN {})", component, channel),
+                None => format!("Ambisonic (ACN {})", channel),
+            };
+        }
+        self.speaker_position(channel)
+            .map(|bit| format!("{} ({})", SPEAKERS[bit].1, SPEAKERS[bit].0))
+            .unwrap_or(String::from("Unassigned"))
+    }
+
+    // The speaker position (its bit in the mask, and index into SPEAKERS) of the given channel
+    pub fn speaker_position(&self, channel: u16) -> Option<usize> {
+        let mask = self.channel_mask();
+        (0..SPEAKERS.len()).filter(|bit| mask & (1 << bit) != 0).nth(channel as usize)
+    }
+
+    fn extensible(&self) -> bool {
+        self.layout.is_some() || self.channels > 2
+            || (self.sample_format == SampleFormat::Int && self.bits_per_sample > 16)
+    }
+
+    pub fn bytes_per_sample(&self) -> u16 {
+        self.bits_per_sample.div_ceil(8)
+    }
+}
+
+// Writes interleaved samples to a WAV file, then fills in the chunk sizes once it's finalized.
+// Until then the file is written next to its destination with a ".part" extension, and a writer
+// dropped without being finalized removes it, so a failed render never leaves a broken file behind.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    path: String,
+    finished: bool,
+    spec: Spec,
+    container: Container,
+    chunks: Vec<Chunk>,
+    header_len: usize,
+    data_len: u64,
+}
+
+impl WavWriter {
+    pub fn create(path: &str, spec: Spec, container: Container, metadata: &Metadata) -> io::Result<WavWriter> {
+        if !spec.sample_format.supports(spec.bits_per_sample) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported bit depth"));
+        }
+        let mut file = BufWriter::new(File::create(path.to_string() + ".part")?);
+        let chunks = chunks(&spec, metadata);
+        let header = header(&spec, container, 0, &chunks);
+        file.write_all(&header)?;
+        Ok(WavWriter { file, path: path.to_string(), finished: false, spec, container, chunks, header_len: header.len(), data_len: 0 })
+    }
+
+    // Write one integer sample, already quantized to the bit depth
+    pub fn write_int(&mut self, sample: i32) -> io::Result<()> {
+        self.count_sample()?;
+        write_int(&mut self.file, &self.spec, sample)
+    }
+
+    // Write one float sample, where 1.0 is full scale
+    pub fn write_float(&mut self, sample: f64) -> io::Result<()> {
+        self.count_sample()?;
+        write_float(&mut self.file, &self.spec, sample)
+    }
+
+    // Add one sample to the data length, which has to fit in the container's size fields
+    fn count_sample(&mut self) -> io::Result<()> {
+        self.data_len += self.spec.bytes_per_sample() as u64;
+        if !self.container.fits(self.header_len, self.data_len) {
+            return Err(io::Error::new(io::ErrorKind::FileTooLarge, "too much data for a WAV file, use rf64 or w64"));
+        }
+        Ok(())
+    }
+
+    // Pad the data chunk to the container's alignment, write the header again with every size filled
+    // in, then move the file into place. The header is the same length whatever the sizes are.
+    pub fn finalize(mut self) -> io::Result<()> {
+        self.file.write_all(&vec![0; self.container.padding(self.data_len) as usize])?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header(&self.spec, self.container, self.data_len, &self.chunks))?;
+        self.file.flush()?;
+        fs::rename(self.path.clone() + ".part", &self.path)?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = fs::remove_file(self.path.clone() + ".part");
+        }
+    }
+}
+
+// Writes the same samples as a WavWriter to a stream that can't seek back, such as stdout. A WAV
+// header up front gives the length when it's known and fits, and the largest length there is
+// otherwise, which readers take to mean "until the stream ends". Raw PCM has no header at all.
+pub struct StreamWriter {
+    out: BufWriter<Box<dyn Write>>,
+    spec: Spec,
+    padding: u64,
+}
+
+impl StreamWriter {
+    pub fn create(out: Box<dyn Write>, spec: Spec, container: Option<Container>, frames: Option<u64>, metadata: &Metadata) -> io::Result<StreamWriter> {
+        if !spec.sample_format.supports(spec.bits_per_sample) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported bit depth"));
+        }
+        let mut out = BufWriter::with_capacity(1 << 16, out);
+        let mut padding = 0;
+        if let Some(container) = container {
+            let block_align = (spec.bytes_per_sample() * spec.channels) as u64;
+            let data_len = frames.filter(|frames| container.holds(&spec, metadata, *frames))
+                .map(|frames| frames * block_align)
+                .unwrap_or(u64::MAX);
+            if data_len != u64::MAX {padding = container.padding(data_len)}
+            out.write_all(&header(&spec, container, data_len, &chunks(&spec, metadata)))?;
+        }
+        Ok(StreamWriter { out, spec, padding })
+    }
+
+    pub fn write_int(&mut self, sample: i32) -> io::Result<()> {
+        write_int(&mut self.out, &self.spec, sample)
+    }
+
+    pub fn write_float(&mut self, sample: f64) -> io::Result<()> {
+        write_float(&mut self.out, &self.spec, sample)
+    }
+
+    // Nothing is left to fill in, so this only pads a header's data chunk and flushes
+    pub fn finalize(mut self) -> io::Result<()> {
+        self.out.write_all(&vec![0; self.padding as usize])?;
+        self.out.flush()
+    }
+}
+
+// Everything before the samples: the container's header, the fmt chunk, a fact chunk for float,
+// any other chunks, and the data chunk header. A data length of u64::MAX is an unknown length, and
+// fills every size field. The fmt chunk is the older PCMWAVEFORMAT or WAVEFORMATEX wherever that
+// is allowed, as above.
+fn header(spec: &Spec, container: Container, data_len: u64, others: &[Chunk]) -> Vec<u8> {
+    let float = spec.sample_format == SampleFormat::Float;
+    let bytes_per_sample = spec.bytes_per_sample();
+    let block_align = bytes_per_sample * spec.channels;
+    let unknown = data_len == u64::MAX;
+
+    let mut fmt: Vec<u8> = Vec::new();
+    let format_tag = match (spec.extensible(), float) {
+        (true, _) => 0xfffeu16,
+        (false, true) => 3,
+        (false, false) => 1,
+    };
+    fmt.extend_from_slice(&format_tag.to_le_bytes());
+    fmt.extend_from_slice(&spec.channels.to_le_bytes());
+    fmt.extend_from_slice(&spec.sample_rate.to_le_bytes());
+    fmt.extend_from_slice(&(spec.sample_rate * block_align as u32).to_le_bytes());
+    fmt.extend_from_slice(&block_align.to_le_bytes());
+    if spec.extensible() {
+        // The container size, then cbSize, the real bit depth, the mask, and the SubFormat
+        fmt.extend_from_slice(&(bytes_per_sample * 8).to_le_bytes());
+        fmt.extend_from_slice(&22u16.to_le_bytes());
+        fmt.extend_from_slice(&spec.bits_per_sample.to_le_bytes());
+        fmt.extend_from_slice(&spec.channel_mask().to_le_bytes());
+        fmt.extend_from_slice(if float {&SUBTYPE_IEEE_FLOAT} else {&SUBTYPE_PCM});
+    } else {
+        fmt.extend_from_slice(&spec.bits_per_sample.to_le_bytes());
+        if float {fmt.extend_from_slice(&0u16.to_le_bytes())}
+    }
+
+    // The fact chunk holds the length in frames, which RF64 leaves to its ds64 chunk
+    let frames = if unknown {u64::MAX} else {data_len / block_align as u64};
+    let mut chunks: Vec<Chunk> = vec![(b"fmt ", fmt)];
+    if float {
+        let fact = if container == Container::Rf64 {u32::MAX} else {frames.min(u32::MAX as u64) as u32};
+        chunks.push((b"fact", fact.to_le_bytes().to_vec()));
+    }
+    chunks.extend_from_slice(others);
+
+    let mut header: Vec<u8> = Vec::new();
+    match container {
+        Container::Riff | Container::Rf64 => {
+            header.extend_from_slice(if container == Container::Rf64 {b"RF64"} else {b"RIFF"});
+            header.extend_from_slice(&0u32.to_le_bytes());
+            header.extend_from_slice(b"WAVE");
+            if container == Container::Rf64 {
+                // The RIFF size, data size, and frame count, then an empty table of other sizes
+                header.extend_from_slice(b"ds64");
+                header.extend_from_slice(&28u32.to_le_bytes());
+                header.extend_from_slice(&[0; 28]);
+            }
+            for (id, body) in chunks {
+                header.extend_from_slice(id);
+                header.extend_from_slice(&(body.len() as u32).to_le_bytes());
+                header.extend_from_slice(&body);
+                header.resize(header.len() + container.padding(body.len() as u64) as usize, 0);
+            }
+            header.extend_from_slice(b"data");
+            header.extend_from_slice(&0u32.to_le_bytes());
+
+            // RF64 sets its 32 bit sizes to the largest there is, and gives the real ones in ds64
+            let riff_len = if unknown {u64::MAX} else {header.len() as u64 - 8 + data_len + data_len % 2};
+            let data_len_offset = header.len() - 4;
+            if container == Container::Rf64 {
+                header[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+                header[data_len_offset..].copy_from_slice(&u32::MAX.to_le_bytes());
+                header[20..28].copy_from_slice(&riff_len.to_le_bytes());
+                header[28..36].copy_from_slice(&data_len.to_le_bytes());
+                header[36..44].copy_from_slice(&frames.to_le_bytes());
+            } else {
+                header[4..8].copy_from_slice(&(riff_len.min(u32::MAX as u64) as u32).to_le_bytes());
+                header[data_len_offset..].copy_from_slice(&(data_len.min(u32::MAX as u64) as u32).to_le_bytes());
+            }
+        }
+        Container::Wave64 => {
+            // Every size counts the 24 bytes of the chunk's own GUID and size
+            header.extend_from_slice(&W64_RIFF);
+            header.extend_from_slice(&0u64.to_le_bytes());
+            header.extend_from_slice(&w64_guid(b"wave"));
+            for (id, body) in chunks {
+                header.extend_from_slice(&w64_guid(id));
+                header.extend_from_slice(&(24 + body.len() as u64).to_le_bytes());
+                header.extend_from_slice(&body);
+                header.resize(header.len() + container.padding(body.len() as u64) as usize, 0);
+            }
+            header.extend_from_slice(&w64_guid(b"data"));
+            header.extend_from_slice(&(if unknown {u64::MAX} else {24 + data_len}).to_le_bytes());
+            let riff_len = if unknown {u64::MAX} else {header.len() as u64 + data_len + container.padding(data_len)};
+            header[16..24].copy_from_slice(&riff_len.to_le_bytes());
+        }
+    }
+    header
+}
+
+// The Wave64 GUID of a chunk, from its RIFF name. The list chunk has its own, like the riff chunk.
+fn w64_guid(id: &[u8; 4]) -> [u8; 16] {
+    if id == b"LIST" {return W64_LIST}
+    let mut guid = [0u8; 16];
+    guid[..4].copy_from_slice(id);
+    guid[4..].copy_from_slice(&W64_SUFFIX);
+    guid
+}
+
+// A chunk's RIFF name and its body, ready to be written in any of the containers
+type Chunk = (&'static [u8; 4], Vec<u8>);
+
+// The chunks describing a render, when there's anything to describe, then its loop and markers if
+// it has any
+fn chunks(spec: &Spec, metadata: &Metadata) -> Vec<Chunk> {
+    let mut chunks: Vec<Chunk> = Vec::new();
+    if !metadata.tags.is_empty() {
+        chunks.push((b"bext", bext(spec, metadata)));
+        chunks.push((b"LIST", info(metadata)));
+    }
+    if let Some(looped) = metadata.looped {chunks.push((b"smpl", smpl(spec, &looped)))}
+    if !metadata.markers.is_empty() {
+        chunks.push((b"cue ", cue(&metadata.markers)));
+        chunks.push((b"LIST", adtl(&metadata.markers)));
+    }
+    chunks
+}
+
+// The Broadcast WAV bext chunk, version 1: the command line as the description, homados as the
+// originator, the date and time of the render in UTC, and its time reference. Its text fields are
+// ASCII, padded out with NULs, so a render without a date leaves them empty, as it does the UMID. The coding history that ends it gives
+// the format of the samples, in the style of EBU R98.
+fn bext(spec: &Spec, metadata: &Metadata) -> Vec<u8> {
+    let text = |text: &str, len: usize| {
+        let mut field: Vec<u8> = text.chars().map(|c| if c.is_ascii() {c as u8} else {b'?'}).take(len).collect();
+        field.resize(len, 0);
+        field
+    };
+    let (date, time) = metadata.tag("DATE").and_then(|date| date.split_once('T')).unwrap_or_default();
+    let mut bext: Vec<u8> = Vec::new();
+    bext.extend(text(metadata.tag("COMMAND").unwrap_or_default(), 256));
+    bext.extend(text(metadata.tag("ENCODER").unwrap_or_default(), 32));
+    bext.extend(text("", 32));
+    bext.extend(text(date, 10));
+    bext.extend(text(time.trim_end_matches('Z'), 8));
+    bext.extend_from_slice(&metadata.time_reference.to_le_bytes());
+    bext.extend_from_slice(&1u16.to_le_bytes());
+    bext.extend_from_slice(&[0; 64 + 190]);
+
+    let mode = match spec.channels {1 => ",M=mono", 2 => ",M=stereo", _ => ""};
+    let algorithm = match spec.sample_format {SampleFormat::Int => "PCM", SampleFormat::Float => "FLOAT"};
+    bext.extend(format!("A={},F={},W={}{},T={}\r\n", algorithm, spec.sample_rate, spec.bits_per_sample, mode,
+        metadata.tag("ENCODER").unwrap_or_default()).bytes());
+
+    // A NUL evens out the length, as some readers skip chunks without their pad byte
+    if bext.len() % 2 == 1 {bext.push(0)}
+    bext
+}
+
+// The sampler chunk [21], holding one forward loop that repeats until the note is released. Unlike
+// the Loop it's written from, its end is the last frame played. The unity note is the MIDI note
+// at or just below the loop's frequency, and the pitch fraction how far above it the frequency is,
+// in 2^32ths of a semitone. The sample period is in nanoseconds, and there's no SMPTE offset.
+fn smpl(spec: &Spec, looped: &Loop) -> Vec<u8> {
+    let note = (69.0 + 12.0 * (looped.frequency / 440.0).log2()).clamp(0.0, 127.0);
+    let fraction = ((note - note.floor()) * 2.0f64.powi(32)).min(u32::MAX as f64) as u32;
+    let period = (1e9 / spec.sample_rate as f64).round() as u32;
+    let fields: [u32; 9] = [0, 0, period, note.floor() as u32, fraction, 0, 0, 1, 0];
+    let loop_fields: [u32; 6] = [0, 0, looped.start, looped.end.saturating_sub(1), 0, 0];
+    fields.iter().chain(loop_fields.iter()).flat_map(|field| field.to_le_bytes()).collect()
+}
+
+// The cue chunk [20], with a cue point at each marker, numbered from 1. With no playlist, a cue
+// point's position is the same frame as its offset into the data chunk.
+fn cue(markers: &[Marker]) -> Vec<u8> {
+    let mut cue: Vec<u8> = (markers.len() as u32).to_le_bytes().to_vec();
+    for (id, marker) in markers.iter().enumerate() {
+        cue.extend_from_slice(&(id as u32 + 1).to_le_bytes());
+        cue.extend_from_slice(&marker.position.to_le_bytes());
+        cue.extend_from_slice(b"data");
+        cue.extend_from_slice(&[0; 8]);
+        cue.extend_from_slice(&marker.position.to_le_bytes());
+    }
+    cue
+}
+
+// The LIST adtl chunk [20], naming each cue point with a NUL terminated label, padded to an even
+// length
+fn adtl(markers: &[Marker]) -> Vec<u8> {
+    let mut list: Vec<u8> = b"adtl".to_vec();
+    for (id, marker) in markers.iter().enumerate() {
+        list.extend_from_slice(b"labl");
+        list.extend_from_slice(&(4 + marker.label.len() as u32 + 1).to_le_bytes());
+        list.extend_from_slice(&(id as u32 + 1).to_le_bytes());
+        list.extend_from_slice(marker.label.as_bytes());
+        list.push(0);
+        if list.len() % 2 == 1 {list.push(0)}
+    }
+    list
+}
+
+// The LIST INFO chunk: the title, software, and creation date where readers look for them, then
+// every tag as a "NAME=value" line of the comment, so the whole render can be read back. Each
+// text is NUL terminated, and padded to an even length.
+fn info(metadata: &Metadata) -> Vec<u8> {
+    let comment: Vec<String> = metadata.tags.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    let date = metadata.tag("DATE").map(|date| date.split('T').next().unwrap_or_default());
+    let mut list: Vec<u8> = b"INFO".to_vec();
+    for (id, text) in [(b"INAM", metadata.tag("TITLE")), (b"ISFT", metadata.tag("ENCODER")), (b"ICRD", date),
+            (b"ICMT", Some(comment.join("\n").as_str()))] {
+        let Some(text) = text.filter(|text| !text.is_empty()) else {continue};
+        list.extend_from_slice(id);
+        list.extend_from_slice(&(text.len() as u32 + 1).to_le_bytes());
+        list.extend_from_slice(text.as_bytes());
+        list.push(0);
+        if list.len() % 2 == 1 {list.push(0)}
+    }
+    list
+}
+
+// One integer sample, already quantized to the bit depth. 8 bit WAV is unsigned, so it is offset.
+fn write_int(out: &mut impl Write, spec: &Spec, sample: i32) -> io::Result<()> {
+    let bytes_per_sample = spec.bytes_per_sample() as usize;
+    if bytes_per_sample == 1 {
+        out.write_all(&[(sample + 128) as u8])
+    } else {
+        out.write_all(&sample.to_le_bytes()[..bytes_per_sample])
+    }
+}
+
+// One float sample, where 1.0 is full scale. It is written as it is, over-range or not.
+fn write_float(out: &mut impl Write, spec: &Spec, sample: f64) -> io::Result<()> {
+    if spec.bytes_per_sample() == 4 {
+        out.write_all(&(sample as f32).to_le_bytes())
+    } else {
+        out.write_all(&sample.to_le_bytes())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write a short file and read its fmt chunk back: the channel count, and the mask of a
+    // WAVEFORMATEXTENSIBLE fmt chunk, if it is one
+    fn written_fmt(name: &str, channels: u16, layout: Option<Layout>) -> (u16, Option<u32>) {
+        let spec = Spec { channels, sample_rate: 48000, bits_per_sample: 24, sample_format: SampleFormat::Int, layout };
+        let path = std::env::temp_dir().join(format!("homados-test-{}-{}.wav", std::process::id(), name));
+        let path = path.to_str().unwrap();
+        let mut writer = WavWriter::create(path, spec, Container::Riff, &Metadata::default()).unwrap();
+        for _ in 0 .. 10 * channels {
+            writer.write_int(0).unwrap();
+        }
+        writer.finalize().unwrap();
+        let file = fs::read(path).unwrap();
+        let _ = fs::remove_file(path);
+
+        assert_eq!(&file[12..16], b"fmt ");
+        let fmt = &file[20 .. 20 + u32::from_le_bytes(file[16..20].try_into().unwrap()) as usize];
+        let word = |at: usize| u16::from_le_bytes(fmt[at .. at + 2].try_into().unwrap());
+        let mask = (word(0) == 0xfffe).then(|| u32::from_le_bytes(fmt[20..24].try_into().unwrap()));
+        (word(2), mask)
+    }
+
+    #[test]
+    fn layout_channel_masks() {
+        for (layout, channels, mask) in [(Layout::Mono, 1, 0x4), (Layout::Stereo, 2, 0x3), (Layout::Surround51, 6, 0x60f),
+                (Layout::Surround71, 8, 0x63f), (Layout::Surround714, 12, 0x2d63f)] {
+            assert_eq!(written_fmt(layout.name(), channels, Some(layout)), (channels, Some(mask)), "{}", layout.name());
+        }
+    }
+
+    // Ambisonics feed no speaker, so their mask is empty whatever their channel count
+    #[test]
+    fn ambisonic_channel_counts() {
+        for (layout, channels) in [(Layout::Ambisonic1, 4), (Layout::Ambisonic2, 9), (Layout::Ambisonic3, 16)] {
+            assert_eq!(layout.channels(), channels);
+            assert_eq!(written_fmt(layout.name(), channels, Some(layout)), (channels, Some(0)), "{}", layout.name());
+        }
+    }
+
+    // Without a layout, channels take the speakers in mask order, except a lone channel, which
+    // feeds the front centre just as the mono layout does
+    #[test]
+    fn default_channel_masks() {
+        assert_eq!(written_fmt("default-mono", 1, None), written_fmt("mono-layout", 1, Some(Layout::Mono)));
+        assert_eq!(written_fmt("default-stereo", 2, None), (2, Some(0x3)));
+        assert_eq!(written_fmt("default-6", 6, None), (6, Some(0x3f)));
+    }
+}
@@ -16,91 +16,207 @@
 
 use std::f64::consts::{E, TAU, PI, FRAC_PI_2};
 
-// Duration here is seen as sd - 1, as we wish to guarantee that the initial and final sample
-// scalars are the actual intended endpoint values (0 or 1) of our functions. If we don't do this,
-// the functions may reach their target value 1 sample "after" the end of the output, which will
-// cause issues in a variety of scenarios.
-pub fn match_window(window: &str, k: f64, sd: u32, x: f64) -> f64 {
-    let d: f64 = sd as f64 - 1.0;
-    match window {
-        "default" | "def" | "flat" | "unity" | "full" | "none" | "constant" | "const" => 1.0,
-        "linear_out" | "lin_out" => linear_out(d, x),
-        "linear_in" | "lin_in" => linear_in(d, x),
-        "linear_io" | "lin_io" => linear_io(d, x),
-        "linear_oi" | "lin_oi" => linear_oi(d, x),
-        "exp1_out" | "exp_out" => exp1_out(d, x, k),
-        "exp1_in" | "exp_in" => exp1_in(d, x, k),
-        "exp1_io" | "exp_io" => exp1_io(d, x, k),
-        "exp1_oi" | "exp_oi" => exp1_oi(d, x, k),
-        "exp2_out" => exp2_out(d, x),
-        "exp2_in" => exp2_in(d, x),
-        "exp2_io" => exp2_io(d, x),
-        "exp2_oi" => exp2_oi(d, x),
-        "exp3_out" => exp3_out(d, x, k),
-        "exp3_in" => exp3_in(d, x, k),
-        "exp3_io" => exp3_io(d, x, k),
-        "exp3_oi" => exp3_oi(d, x, k),
-        "exp4_out" => exp4_out(d, x, k),
-        "exp4_in" => exp4_in(d, x, k),
-        "exp4_io" => exp4_io(d, x, k),
-        "exp4_oi" => exp4_oi(d, x, k),
-        "exp5_out" => exp5_out(d, x),
-        "exp5_in" => exp5_in(d, x),
-        "exp5_io" => exp5_io(d, x),
-        "exp5_oi" => exp5_oi(d, x),
-        "log1_out" | "log_out" => log1_out(d, x),
-        "log1_in" | "log_in" => log1_in(d, x),
-        "log1_io" | "log_io" => log1_io(d, x),
-        "log1_oi" | "log_oi" => log1_oi(d, x),
-        "log2_out" => log2_out(d, x),
-        "log2_in" => log2_in(d, x),
-        "log2_io" => log2_io(d, x),
-        "log2_oi" => log2_oi(d, x),
-        "eqp1_out" | "eqp_out" => eqp1_out(d, x),
-        "eqp1_in" | "eqp_in" => eqp1_in(d, x),
-        "eqp1_io" | "eqp_io" => eqp1_io(d, x),
-        "eqp1_oi" | "eqp_oi" => eqp1_oi(d, x),
-        "eqp2_out" => eqp2_out(d, x),
-        "eqp2_in" => eqp2_in(d, x),
-        "eqp2_io" => eqp2_io(d, x),
-        "eqp2_oi" => eqp2_oi(d, x),
-        "sc1_out" | "sc_out" | "s1_out" | "s_out" => sc1_out(d, x),
-        "sc1_in" | "sc_in" | "s1_in" | "s_in" => sc1_in(d, x),
-        "sc1_io" | "sc_io" | "s1_io" | "s_io" => sc1_io(d, x),
-        "sc1_oi" | "sc_oi" | "s1_oi" | "s_oi" => sc1_oi(d, x),
-        "sc2_out" | "s2_out" => sc2_out(d, x, k),
-        "sc2_in" | "s2_in" => sc2_in(d, x, k),
-        "sc2_io" | "s2_io" => sc2_io(d, x, k),
-        "sc2_oi" | "s2_oi" => sc2_oi(d, x, k),
-        "sc3_out" | "s3_out" => sc3_out(d, x, k),
-        "sc3_in" | "s3_in" => sc3_in(d, x, k),
-        "sc3_io" | "s3_io" => sc3_io(d, x, k),
-        "sc3_oi" | "s3_oi" => sc3_oi(d, x, k),
-        "sc4_out" | "s4_out" => sc4_out(d, x),
-        "sc4_in" | "s4_in" => sc4_in(d, x),
-        "sc4_io" | "s4_io" => sc4_io(d, x),
-        "sc4_oi" | "s4_oi" => sc4_oi(d, x),
-        "chs_out" | "smoothstep_out" => chs_out(d, x),
-        "chs_in" | "smoothstep_in" => chs_in(d, x),
-        "chs_io" | "smoothstep_io" => chs_io(d, x),
-        "chs_oi" | "smoothstep_oi" => chs_oi(d, x),
-        "chsg_out" => chsg_out(d, x, k),
-        "chsg_in" => chsg_in(d, x, k),
-        "chsg_io" => chsg_io(d, x, k),
-        "chsg_oi" => chsg_oi(d, x, k),
-        "sscf_out" => sscf_out(d, x),
-        "sscf_in" => sscf_in(d, x),
-        "sscf_io" => sscf_io(d, x),
-        "sscf_oi" => sscf_oi(d, x),
-        "tet_out" => tet_out(d, x),
-        "tet_in" => tet_in(d, x),
-        "tet_io" => tet_io(d, x),
-        "tet_oi" => tet_oi(d, x),
-        "slg_out" => slg_out(d, x),
-        "slg_in" => slg_in(d, x),
-        "slg_io" => slg_io(d, x),
-        "slg_oi" => slg_oi(d, x),
-        _ => panic!("\n\n\tError:\tUnrecognized window type.\n\n")
+// Every window is a curve shape paired with a direction. Names are resolved to one of these once,
+// up front, so rendering never has to look at strings again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Flat, Linear, Exp1, Exp2, Exp3, Exp4, Exp5, Log1, Log2, Eqp1, Eqp2,
+    Sc1, Sc2, Sc3, Sc4, Chs, Chsg, Sscf, Tet, Slg,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction { Out, In, Io, Oi }
+
+// The flat window stands alone, every other window name is "<shape>_<direction>".
+// The first name of each entry is its canonical name.
+pub const FLAT_NAMES: &[&str] = &["flat", "def", "default", "unity", "full", "none", "constant", "const"];
+
+pub const SHAPES: &[(Shape, &[&str])] = &[
+    (Shape::Linear, &["lin", "linear"]),
+    (Shape::Exp1, &["exp1", "exp"]),
+    (Shape::Exp2, &["exp2"]),
+    (Shape::Exp3, &["exp3"]),
+    (Shape::Exp4, &["exp4"]),
+    (Shape::Exp5, &["exp5"]),
+    (Shape::Log1, &["log1", "log"]),
+    (Shape::Log2, &["log2"]),
+    (Shape::Eqp1, &["eqp1", "eqp"]),
+    (Shape::Eqp2, &["eqp2"]),
+    (Shape::Sc1, &["sc1", "sc", "s1", "s"]),
+    (Shape::Sc2, &["sc2", "s2"]),
+    (Shape::Sc3, &["sc3", "s3"]),
+    (Shape::Sc4, &["sc4", "s4"]),
+    (Shape::Chs, &["chs", "smoothstep"]),
+    (Shape::Chsg, &["chsg"]),
+    (Shape::Sscf, &["sscf"]),
+    (Shape::Tet, &["tet"]),
+    (Shape::Slg, &["slg"]),
+];
+
+pub const DIRECTIONS: &[(Direction, &str)] = &[
+    (Direction::Out, "out"),
+    (Direction::In, "in"),
+    (Direction::Io, "io"),
+    (Direction::Oi, "oi"),
+];
+
+// A gain envelope / "fade window", prepared once and then read sample by sample
+#[derive(Clone, Copy, Debug)]
+pub struct Window {
+    pub shape: Shape,
+    pub direction: Direction,
+    pub k: f64,
+    d: f64,
+}
+
+impl Window {
+    pub fn new(shape: Shape, direction: Direction, k: f64) -> Window {
+        Window { shape, direction, k, d: 0.0 }
+    }
+
+    // Look up a window by any of its names, with k as the curve modifier
+    pub fn from_name(name: &str, k: f64) -> Option<Window> {
+        if FLAT_NAMES.contains(&name) {
+            return Some(Window::new(Shape::Flat, Direction::Out, k));
+        }
+        let (shape_name, direction_name) = name.rsplit_once('_')?;
+        let shape = SHAPES.iter().find(|(_, names)| names.contains(&shape_name))?.0;
+        let direction = DIRECTIONS.iter().find(|(_, n)| *n == direction_name)?.0;
+        Some(Window::new(shape, direction, k))
+    }
+}
+
+// Anything that can scale a signal over its duration. The built-in windows all go through Window,
+// but anything implementing this can be registered and rendered the same way.
+pub trait Envelope {
+    // Set the duration (in samples) that the envelope will be stretched across
+    fn prepare(&mut self, duration: u64);
+
+    // The gain scalar at sample x
+    fn gain(&self, x: f64) -> f64;
+
+    // Name shown in verbose output
+    fn name(&self) -> String {
+        String::from("custom")
+    }
+
+    // Labelled points along the envelope, each as how far through it they are, from 0 to 1
+    fn markers(&self) -> Vec<(f64, String)> {
+        Vec::new()
+    }
+}
+
+impl Envelope for Window {
+    // Duration here is seen as sd - 1, as we wish to guarantee that the initial and final sample
+    // scalars are the actual intended endpoint values (0 or 1) of our functions. If we don't do
+    // this, the functions may reach their target value 1 sample "after" the end of the output,
+    // which will cause issues in a variety of scenarios.
+    fn prepare(&mut self, sd: u64) {
+        self.d = sd as f64 - 1.0;
+    }
+
+    fn gain(&self, x: f64) -> f64 {
+        let (d, k) = (self.d, self.k);
+        match (self.shape, self.direction) {
+            (Shape::Flat, _) => 1.0,
+            (Shape::Linear, Direction::Out) => linear_out(d, x),
+            (Shape::Linear, Direction::In) => linear_in(d, x),
+            (Shape::Linear, Direction::Io) => linear_io(d, x),
+            (Shape::Linear, Direction::Oi) => linear_oi(d, x),
+            (Shape::Exp1, Direction::Out) => exp1_out(d, x, k),
+            (Shape::Exp1, Direction::In) => exp1_in(d, x, k),
+            (Shape::Exp1, Direction::Io) => exp1_io(d, x, k),
+            (Shape::Exp1, Direction::Oi) => exp1_oi(d, x, k),
+            (Shape::Exp2, Direction::Out) => exp2_out(d, x),
+            (Shape::Exp2, Direction::In) => exp2_in(d, x),
+            (Shape::Exp2, Direction::Io) => exp2_io(d, x),
+            (Shape::Exp2, Direction::Oi) => exp2_oi(d, x),
+            (Shape::Exp3, Direction::Out) => exp3_out(d, x, k),
+            (Shape::Exp3, Direction::In) => exp3_in(d, x, k),
+            (Shape::Exp3, Direction::Io) => exp3_io(d, x, k),
+            (Shape::Exp3, Direction::Oi) => exp3_oi(d, x, k),
+            (Shape::Exp4, Direction::Out) => exp4_out(d, x, k),
+            (Shape::Exp4, Direction::In) => exp4_in(d, x, k),
+            (Shape::Exp4, Direction::Io) => exp4_io(d, x, k),
+            (Shape::Exp4, Direction::Oi) => exp4_oi(d, x, k),
+            (Shape::Exp5, Direction::Out) => exp5_out(d, x),
+            (Shape::Exp5, Direction::In) => exp5_in(d, x),
+            (Shape::Exp5, Direction::Io) => exp5_io(d, x),
+            (Shape::Exp5, Direction::Oi) => exp5_oi(d, x),
+            (Shape::Log1, Direction::Out) => log1_out(d, x),
+            (Shape::Log1, Direction::In) => log1_in(d, x),
+            (Shape::Log1, Direction::Io) => log1_io(d, x),
+            (Shape::Log1, Direction::Oi) => log1_oi(d, x),
+            (Shape::Log2, Direction::Out) => log2_out(d, x),
+            (Shape::Log2, Direction::In) => log2_in(d, x),
+            (Shape::Log2, Direction::Io) => log2_io(d, x),
+            (Shape::Log2, Direction::Oi) => log2_oi(d, x),
+            (Shape::Eqp1, Direction::Out) => eqp1_out(d, x),
+            (Shape::Eqp1, Direction::In) => eqp1_in(d, x),
+            (Shape::Eqp1, Direction::Io) => eqp1_io(d, x),
+            (Shape::Eqp1, Direction::Oi) => eqp1_oi(d, x),
+            (Shape::Eqp2, Direction::Out) => eqp2_out(d, x),
+            (Shape::Eqp2, Direction::In) => eqp2_in(d, x),
+            (Shape::Eqp2, Direction::Io) => eqp2_io(d, x),
+            (Shape::Eqp2, Direction::Oi) => eqp2_oi(d, x),
+            (Shape::Sc1, Direction::Out) => sc1_out(d, x),
+            (Shape::Sc1, Direction::In) => sc1_in(d, x),
+            (Shape::Sc1, Direction::Io) => sc1_io(d, x),
+            (Shape::Sc1, Direction::Oi) => sc1_oi(d, x),
+            (Shape::Sc2, Direction::Out) => sc2_out(d, x, k),
+            (Shape::Sc2, Direction::In) => sc2_in(d, x, k),
+            (Shape::Sc2, Direction::Io) => sc2_io(d, x, k),
+            (Shape::Sc2, Direction::Oi) => sc2_oi(d, x, k),
+            (Shape::Sc3, Direction::Out) => sc3_out(d, x, k),
+            (Shape::Sc3, Direction::In) => sc3_in(d, x, k),
+            (Shape::Sc3, Direction::Io) => sc3_io(d, x, k),
+            (Shape::Sc3, Direction::Oi) => sc3_oi(d, x, k),
+            (Shape::Sc4, Direction::Out) => sc4_out(d, x),
+            (Shape::Sc4, Direction::In) => sc4_in(d, x),
+            (Shape::Sc4, Direction::Io) => sc4_io(d, x),
+            (Shape::Sc4, Direction::Oi) => sc4_oi(d, x),
+            (Shape::Chs, Direction::Out) => chs_out(d, x),
+            (Shape::Chs, Direction::In) => chs_in(d, x),
+            (Shape::Chs, Direction::Io) => chs_io(d, x),
+            (Shape::Chs, Direction::Oi) => chs_oi(d, x),
+            (Shape::Chsg, Direction::Out) => chsg_out(d, x, k),
+            (Shape::Chsg, Direction::In) => chsg_in(d, x, k),
+            (Shape::Chsg, Direction::Io) => chsg_io(d, x, k),
+            (Shape::Chsg, Direction::Oi) => chsg_oi(d, x, k),
+            (Shape::Sscf, Direction::Out) => sscf_out(d, x),
+            (Shape::Sscf, Direction::In) => sscf_in(d, x),
+            (Shape::Sscf, Direction::Io) => sscf_io(d, x),
+            (Shape::Sscf, Direction::Oi) => sscf_oi(d, x),
+            (Shape::Tet, Direction::Out) => tet_out(d, x),
+            (Shape::Tet, Direction::In) => tet_in(d, x),
+            (Shape::Tet, Direction::Io) => tet_io(d, x),
+            (Shape::Tet, Direction::Oi) => tet_oi(d, x),
+            (Shape::Slg, Direction::Out) => slg_out(d, x),
+            (Shape::Slg, Direction::In) => slg_in(d, x),
+            (Shape::Slg, Direction::Io) => slg_io(d, x),
+            (Shape::Slg, Direction::Oi) => slg_oi(d, x),
+        }
+    }
+
+    fn name(&self) -> String {
+        if self.shape == Shape::Flat {
+            return FLAT_NAMES[0].to_string();
+        }
+        let shape = SHAPES.iter().find(|(shape, _)| *shape == self.shape).map(|(_, n)| n[0]);
+        let direction = DIRECTIONS.iter().find(|(dir, _)| *dir == self.direction).map(|(_, n)| *n);
+        format!("{}_{}", shape.unwrap_or(""), direction.unwrap_or(""))
+    }
+
+    // The middle of each fade, and where an in-out or out-in window turns around at its halfway point
+    fn markers(&self) -> Vec<(f64, String)> {
+        let points: &[(f64, &str)] = match (self.shape, self.direction) {
+            (Shape::Flat, _) => &[],
+            (_, Direction::In) => &[(0.5, "Fade in midpoint")],
+            (_, Direction::Out) => &[(0.5, "Fade out midpoint")],
+            (_, Direction::Io) => &[(0.25, "Fade in midpoint"), (0.5, "Fade in end"), (0.75, "Fade out midpoint")],
+            (_, Direction::Oi) => &[(0.25, "Fade out midpoint"), (0.5, "Fade out end"), (0.75, "Fade in midpoint")],
+        };
+        points.iter().map(|(position, label)| (*position, label.to_string())).collect()
     }
 }
 
@@ -165,15 +281,15 @@ pub fn exp1_oi(duration: f64, sample: f64, k: f64) -> f64 {
 
 // Exp Curve 2: Gaussian "Bell" Curve Function, "magic numbers" here normalize the output gain
 pub fn exp2_out(duration: f64, sample: f64) -> f64 {
-    (1.00637003594226 / ((E as f64).powf((2.25 * sample / duration).powf(2.0)))) - 0.00637003594226
+    (1.00637003594226 / (E.powf((2.25 * sample / duration).powf(2.0)))) - 0.00637003594226
 }
 
 pub fn exp2_in(duration: f64, sample: f64) -> f64 {
-    (1.00637003594226 / ((E as f64).powf((2.25 * ((sample / duration) - 1.0)).powf(2.0)))) - 0.00637003594226
+    (1.00637003594226 / (E.powf((2.25 * ((sample / duration) - 1.0)).powf(2.0)))) - 0.00637003594226
 }
 
 pub fn exp2_io(duration: f64, sample: f64) -> f64 {
-    (1.00637003594226 / ((E as f64).powf(((4.5 * sample / duration) - 2.25).powf(2.0)))) - 0.00637003594226
+    (1.00637003594226 / (E.powf(((4.5 * sample / duration) - 2.25).powf(2.0)))) - 0.00637003594226
 }
 
 pub fn exp2_oi(duration: f64, sample: f64) -> f64 {
@@ -184,7 +300,7 @@ pub fn exp2_oi(duration: f64, sample: f64) -> f64 {
 
 // Exp Curve 3: Exponential function -- base e with controllable contour
 pub fn exp3_out(duration: f64, sample: f64, k: f64) -> f64 {
-    1.0 - exp3_in(duration, sample, -1.0 * k)
+    1.0 - exp3_in(duration, sample, -k)
 }
 
 pub fn exp3_in(duration: f64, sample: f64, k: f64) -> f64 {
@@ -193,7 +309,7 @@ pub fn exp3_in(duration: f64, sample: f64, k: f64) -> f64 {
 
 pub fn exp3_io(duration: f64, sample: f64, k: f64) -> f64 {
     let x2 = 2.0 * sample;
-    let k2 = -1.0 * k;
+    let k2 = -k;
     if sample <= (duration * 0.5) {
         (E.powf(k * x2 / duration) - 1.0) / (E.powf(k) - 1.0)
     } else {
@@ -203,7 +319,7 @@ pub fn exp3_io(duration: f64, sample: f64, k: f64) -> f64 {
 
 pub fn exp3_oi(duration: f64, sample: f64, k: f64) -> f64 {
     let x2 = 2.0 * sample;
-    let k2 = -1.0 * k;
+    let k2 = -k;
     if sample <= (duration * 0.5) {
         1.0 - ((E.powf(x2 * k2 / duration) - 1.0) / (E.powf(k2) - 1.0))
     } else {